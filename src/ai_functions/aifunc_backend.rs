@@ -6,10 +6,14 @@ pub fn print_backend_webserver_code(_project_description_and_template: &str) {
     /// IMPORTANT: The backend code is ONLY an example. If the Project Description requires it, make as many changes as you like.
     /// IMPORTANT: You do not need to follow the backend code exactly. Write functions that make sense for the users request if required.
     /// FUNCTION: Takes an existing set of code marked as CODE_TEMPLATE and updates or re-writes it to work for the purpose in the PROJECT_DESCRIPTION
-    /// IMPORTANT: The following libraries are already installed
-    ///   reqwest, serde, serde_json, tokio, actix-web, async-trait, actix_cors
-    /// No other external libraries should be used. Write functions that fit with the description from the PROJECT_DESCRIPTION
-    /// OUTPUT: Print ONLY the code, nothing else. This function ONLY prints code.
+    /// IMPORTANT: The server binds to 127.0.0.1 on the port given by the PORT environment variable, defaulting to 1337 when it is not set
+    /// Write functions that fit with the description from the PROJECT_DESCRIPTION
+    /// IMPORTANT: Only the crates in ALLOWED_DEPENDENCIES may be used, at the versions given there. No other external libraries.
+    /// Every crate the code uses that is not yet in Cargo.toml goes in "dependencies" as {"name": "chrono", "version": "", "features": ["serde"]}. An empty version takes the allowed one.
+    /// OUTPUT: Prints ONLY a JSON file manifest, nothing else:
+    ///   {"files": [{"path": "src/main.rs", "contents": "..."}, {"path": "src/routes/todos.rs", "contents": "..."}], "dependencies": []}
+    /// Paths are relative to the project root. Split the service into modules (src/routes/*.rs, src/models.rs, src/db.rs, ...) declared from src/main.rs as it grows.
+    /// Cargo.toml is managed for you through "dependencies". Never write outside the project, into target/, .git/ or .cargo/.
     println!(OUTPUT)
 }
 
@@ -20,22 +24,44 @@ pub fn print_improved_webserver_code(_project_description_and_template: &str) {
     ///   1. Removes any bugs in the code and adds minor additional functionality
     ///   2. Makes sure everything requested in the spec from a backend standpoint was followed. If not, add the feature. No code should be implemented later. Everything should be written now.
     ///   3. ONLY writes the code. No commentary.
-    /// IMPORTANT: The following libraries are already installed. Does not use ANY libraries other than what was provided in the template
-    ///   reqwest, serde, serde_json, tokio, actix-web, async-trait, actix_cors, mongodb, jsonwebtoken, argon2
+    /// IMPORTANT: Only the crates in ALLOWED_DEPENDENCIES may be used, at the versions given there. No other external libraries.
+    /// Every crate the code uses that is not yet in Cargo.toml goes in "dependencies" as {"name": "chrono", "version": "", "features": ["serde"]}. An empty version takes the allowed one.
+    /// IMPORTANT: The server binds to 127.0.0.1 on the port given by the PORT environment variable, defaulting to 1337 when it is not set
+    /// INPUT CODE is given as FILE: blocks, one per project file
+    /// OUTPUT: Prints ONLY a JSON edit plan, nothing else:
+    ///   {"edits": [{"path": "src/main.rs", "search": "lines to replace", "replace": "new lines"}], "files": [], "dependencies": []}
+    /// Each search is copied exactly from the current file, indentation included, and matches only one place. Add surrounding lines to make it unique.
+    /// An edit with an empty search creates a new file containing replace.
+    /// "files" holds complete rewrites as {"path": "...", "contents": "..."}. Only use it for files that change almost entirely, or when asked to.
+    /// Paths are relative to the project root. Split the service into modules (src/routes/*.rs, src/models.rs, src/db.rs, ...) declared from src/main.rs as it grows.
+    /// Cargo.toml is managed for you through "dependencies". Never write outside the project, into target/, .git/ or .cargo/.
+    /// Only the files that change are touched. Everything else stays as it is.
     println!(OUTPUT)
 }
 
 #[ai_function]
 pub fn print_fixed_code(_broken_code_with_bugs: &str) {
-    /// INPUT: Takes in Rust BROKEN_CODE and the ERROR_BUGS found
+    /// INPUT: Takes in Rust BROKEN_CODE as FILE: blocks, one per project file, and the ERROR_BUGS found
+    /// ERROR_BUGS are compiler errors, CLIPPY LINTS THAT MUST BE FIXED, API CONTRACT TEST FAILURES listing routes that returned the wrong status or response shape,
+    ///   or INTEGRATION TEST FAILURES from the tests in src/api_tests.rs with what each failing test printed
     /// FUNCTION: Removes bugs from code
     /// IMPORTANT: Only prints out the new and improved code. No commentary or anything else
+    /// IMPORTANT: Only the crates in ALLOWED_DEPENDENCIES may be used, at the versions given there. No other external libraries.
+    /// Every crate the code uses that is not yet in Cargo.toml goes in "dependencies" as {"name": "chrono", "version": "", "features": ["serde"]}. An empty version takes the allowed one.
+    /// OUTPUT: Prints ONLY a JSON edit plan, nothing else:
+    ///   {"edits": [{"path": "src/main.rs", "search": "lines to replace", "replace": "new lines"}], "files": [], "dependencies": []}
+    /// Each search is copied exactly from the current file, indentation included, and matches only one place. Add surrounding lines to make it unique.
+    /// An edit with an empty search creates a new file containing replace.
+    /// "files" holds complete rewrites as {"path": "...", "contents": "..."}. Only use it for files that change almost entirely, or when asked to.
+    /// Paths are relative to the project root. Split the service into modules (src/routes/*.rs, src/models.rs, src/db.rs, ...) declared from src/main.rs as it grows.
+    /// Cargo.toml is managed for you through "dependencies". Never write outside the project, into target/, .git/ or .cargo/.
+    /// Only the files that change are touched. Everything else stays as it is.
     println!(OUTPUT)
 }
 
 #[ai_function]
 pub fn print_rest_api_endpoints(_code_input: &str) {
-    /// INPUT: Takes in Rust webserver CODE_INPUT based on actix-web
+    /// INPUT: Takes in Rust webserver CODE_INPUT based on actix-web, as FILE: blocks when the project has several files
     /// FUNCTION: Prints out the JSON schema for url endpoints and their respective types
     /// LOGIC: Script analyses all code and can categorize into the following object keys:
     ///   "route": This represents the url path of the endpoint
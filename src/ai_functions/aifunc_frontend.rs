@@ -0,0 +1,25 @@
+use ai_functions::ai_function;
+
+#[ai_function]
+pub fn print_frontend_client_code(_project_description_and_api_endpoints: &str) {
+    /// INPUT: Takes in a PROJECT_DESCRIPTION, the BACKEND_URL of a running webserver and its API_ENDPOINTS as a JSON schema
+    /// FUNCTION: Writes a single page static web client for the backend
+    /// LOGIC: Every route in API_ENDPOINTS gets a UI element (form, button or list) that calls it with fetch()
+    ///   Each fetch() spells out the route path, e.g. fetch(BACKEND_URL + '/item/' + id), rather than passing it through a helper
+    ///   "route": url path to call, relative to BACKEND_URL. Segments in curly braces such as {id} are filled in from user input
+    ///   "method": HTTP method to use
+    ///   "request_body": JSON body to send, built from form inputs
+    ///   "response": shape of the JSON returned, which should be rendered on the page
+    /// IMPORTANT: Output is ONE complete HTML document starting with <!DOCTYPE html>. All CSS in a <style> tag and all JavaScript in a <script> tag.
+    /// IMPORTANT: No external libraries, CDNs or build tools. Plain HTML, CSS and JavaScript only.
+    /// OUTPUT: Print ONLY the HTML document, nothing else. No commentary.
+    println!(OUTPUT)
+}
+
+#[ai_function]
+pub fn print_fixed_frontend_code(_broken_code_with_bugs: &str) {
+    /// INPUT: Takes in a static HTML/JS web client as BROKEN_CODE, the API_ENDPOINTS it must call and the ERROR_BUGS found
+    /// FUNCTION: Fixes the problems listed in ERROR_BUGS so that every endpoint is wired up
+    /// IMPORTANT: Only prints out the complete fixed HTML document. No commentary or anything else
+    println!(OUTPUT)
+}
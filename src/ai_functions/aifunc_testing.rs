@@ -0,0 +1,40 @@
+use ai_functions::ai_function;
+
+#[ai_function]
+pub fn print_api_integration_tests(_backend_code_and_api_endpoints: &str) {
+    /// INPUT: Takes in a PROJECT_DESCRIPTION, the actix-web BACKEND_CODE as FILE: blocks and its API_ENDPOINTS as a JSON schema
+    /// FUNCTION: Writes the Rust module src/api_tests.rs with integration tests for every route in API_ENDPOINTS
+    /// LOGIC: The module is declared from src/main.rs with #[cfg(test)], so `use super::*;` reaches every handler, struct and state type
+    ///   Each test is an #[actix_web::test] async fn that builds the App with test::init_service, registering the same app_data and routes as main
+    ///   Requests are made with test::TestRequest and test::call_service, never over the network
+    ///   "route": url path to call. Segments in curly braces such as {id} are filled with values created earlier in the same test
+    ///   "method": HTTP method to use
+    ///   "request_body": JSON body to send with test::TestRequest::set_json
+    ///   "response": shape of the JSON returned. Assert the status code and that every field is present with the right type
+    /// IMPORTANT: At least one test per route, named after the method and route, e.g. post_item_creates_item
+    /// IMPORTANT: Only use crates the backend already depends on. Tests must not depend on each other or on their order
+    /// OUTPUT: Print ONLY the Rust code of the module, nothing else. No commentary.
+    println!(OUTPUT)
+}
+
+#[ai_function]
+pub fn print_fixed_api_tests(_broken_tests_with_bugs: &str) {
+    /// INPUT: Takes in the Rust test module BROKEN_TESTS, the BACKEND_CODE as FILE: blocks it tests and the ERROR_BUGS found:
+    ///   compiler errors, or failing tests whose own expectations are wrong
+    /// FUNCTION: Fixes the test module so it compiles against the BACKEND_CODE and every test expects what the routes promise.
+    ///   The tests keep checking the same routes
+    /// IMPORTANT: Only prints out the complete fixed module. No commentary or anything else
+    println!(OUTPUT)
+}
+
+#[ai_function]
+pub fn print_wrong_tests(_tests_backend_and_failures: &str) {
+    /// INPUT: Takes in the Rust test module TESTS, the BACKEND_CODE as FILE: blocks, its API_ENDPOINTS as a JSON schema and the TEST_FAILURES of a run
+    /// FUNCTION: Decides for every failing test whether the test is wrong or the backend is
+    /// LOGIC: A test is wrong when it expects something API_ENDPOINTS does not promise, builds the App differently from main,
+    ///   sends a malformed request or depends on another test. Otherwise the backend is at fault
+    /// OUTPUT: Print ONLY a JSON array with the names of the failing tests that are wrong, as TEST_FAILURES lists them,
+    ///   e.g. ["api_tests::post_item_creates_item"].
+    ///   Print [] when every failure is the backend's fault. No commentary.
+    println!(OUTPUT)
+}
@@ -1,3 +1,5 @@
 pub mod aifunc_architect;
 pub mod aifunc_backend;
-pub mod aifunc_managing;
\ No newline at end of file
+pub mod aifunc_frontend;
+pub mod aifunc_managing;
+pub mod aifunc_testing;
\ No newline at end of file
@@ -0,0 +1,320 @@
+use crate::{errors::{BotError, BotResult}, models::general::{llm::{APIChoice, APIMessage, APIResponse, Message, Usage}, schema::OutputSchema}};
+use super::{llm_provider::{LlmProvider, ProviderConfig, TokenSink}, routing::{routed_model, ModelSettings}};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, env, fs, path::PathBuf, sync::Mutex};
+
+// Record/replay of LLM calls, selected from the environment:
+//   LLM_CASSETTE = path of the cassette file
+//   LLM_CASSETTE_MODE = replay (default) | record
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CassetteMode {
+    Record,
+    Replay,
+}
+
+impl CassetteMode {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.trim().to_lowercase().as_str() {
+            "record" => Some(Self::Record),
+            "replay" => Some(Self::Replay),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Interaction {
+    pub key: String,
+    pub model: String,
+    pub request: Vec<Message>,
+    pub response: String,
+    // kept so a replayed run reports the same cost as the recorded one
+    #[serde(default)]
+    pub usage: Option<Usage>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct Cassette {
+    // provider and model the run defaulted to; a replay needs them to resolve routed models the same way
+    #[serde(default)]
+    pub provider: Option<String>,
+    #[serde(default)]
+    pub model: Option<String>,
+    pub interactions: Vec<Interaction>,
+}
+
+impl Cassette {
+    pub fn load(path: &PathBuf) -> BotResult<Self> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let contents = fs::read_to_string(path)?;
+        serde_json::from_str(&contents)
+            .map_err(|e| BotError::Config(format!("invalid cassette {}: {}", path.display(), e)))
+    }
+    pub fn save(&self, path: &PathBuf) -> BotResult<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let contents = serde_json::to_string_pretty(self)
+            .map_err(|e| BotError::Config(format!("could not serialize cassette: {}", e)))?;
+        fs::write(path, contents)?;
+        Ok(())
+    }
+}
+
+// FNV-1a over the serialized model + messages (+ output schema name), stable across builds and platforms
+pub fn request_key(model: &str, messages: &[Message], schema: Option<&OutputSchema>) -> String {
+    let mut payload = serde_json::json!({ "model": model, "messages": messages });
+    if let Some(schema) = schema {
+        payload["schema"] = serde_json::Value::String(schema.name.clone());
+    }
+    let payload = payload.to_string();
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in payload.as_bytes() {
+        hash ^= *byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    format!("{:016x}", hash)
+}
+
+struct CassetteState {
+    cassette: Cassette,
+    // how many times each key has been served, so repeated identical requests replay in order
+    served: HashMap<String, usize>,
+}
+
+pub struct CassetteProvider {
+    mode: CassetteMode,
+    path: PathBuf,
+    config: ProviderConfig,
+    inner: Option<Box<dyn LlmProvider>>,
+    state: Mutex<CassetteState>,
+}
+
+impl CassetteProvider {
+    pub fn record(path: PathBuf, config: ProviderConfig, inner: Box<dyn LlmProvider>) -> BotResult<Self> {
+        let mut cassette = Cassette::load(&path)?;
+        cassette.provider = Some(config.kind.name().to_string());
+        cassette.model = Some(config.model.clone());
+        Ok(Self {
+            mode: CassetteMode::Record,
+            path,
+            config,
+            inner: Some(inner),
+            state: Mutex::new(CassetteState { cassette, served: HashMap::new() }),
+        })
+    }
+    pub fn replay(path: PathBuf, config: ProviderConfig) -> BotResult<Self> {
+        let cassette = Cassette::load(&path)?;
+        Ok(Self {
+            mode: CassetteMode::Replay,
+            path,
+            config,
+            inner: None,
+            state: Mutex::new(CassetteState { cassette, served: HashMap::new() }),
+        })
+    }
+    fn replay_response(&self, key: &str) -> BotResult<(String, Option<Usage>)> {
+        let mut state = self.state.lock().unwrap();
+        let matches: Vec<(String, Option<Usage>)> = state.cassette.interactions.iter()
+            .filter(|i| i.key == key)
+            .map(|i| (i.response.clone(), i.usage))
+            .collect();
+        if matches.is_empty() {
+            return Err(BotError::Transport(format!(
+                "no recorded response for request {} in cassette {}",
+                key,
+                self.path.display()
+            )));
+        }
+        let served = state.served.entry(key.to_string()).or_insert(0);
+        let response = matches[(*served).min(matches.len() - 1)].clone();
+        *served += 1;
+        Ok(response)
+    }
+}
+
+#[async_trait]
+impl LlmProvider for CassetteProvider {
+    fn model(&self) -> &str {
+        &self.config.model
+    }
+    // the same routing in both modes, so a replay looks up the keys the recording wrote
+    fn model_for(&self, settings: &ModelSettings) -> String {
+        routed_model(&self.config, settings)
+    }
+    async fn complete(&self, messages: Vec<Message>, settings: &ModelSettings) -> BotResult<APIResponse> {
+        self.complete_streaming(messages, settings, &|_: &str| {}).await
+    }
+    async fn complete_streaming(&self, messages: Vec<Message>, settings: &ModelSettings, on_token: &TokenSink<'_>) -> BotResult<APIResponse> {
+        self.call(messages, None, settings, on_token).await
+    }
+    async fn complete_structured(
+        &self,
+        messages: Vec<Message>,
+        schema: &OutputSchema,
+        settings: &ModelSettings,
+        on_token: &TokenSink<'_>,
+    ) -> BotResult<APIResponse> {
+        self.call(messages, Some(schema), settings, on_token).await
+    }
+}
+
+impl CassetteProvider {
+    // Recording streams through the real provider; a replay hands over the whole answer at once
+    async fn call(
+        &self,
+        messages: Vec<Message>,
+        schema: Option<&OutputSchema>,
+        settings: &ModelSettings,
+        on_token: &TokenSink<'_>,
+    ) -> BotResult<APIResponse> {
+        // keyed by the model actually used, so per-agent models replay correctly
+        let model = self.model_for(settings);
+        let key = request_key(&model, &messages, schema);
+        let (content, usage) = match (&self.mode, &self.inner) {
+            (CassetteMode::Record, Some(inner)) => {
+                let res = match schema {
+                    Some(schema) => inner.complete_structured(messages.clone(), schema, settings, on_token).await?,
+                    None => inner.complete_streaming(messages.clone(), settings, on_token).await?,
+                };
+                let Some(choice) = res.choices.first() else {
+                    return Err(BotError::Transport(format!("{} answered without any choices", model)));
+                };
+                let content = choice.message.content.clone();
+                let mut state = self.state.lock().unwrap();
+                state.cassette.interactions.push(Interaction {
+                    key,
+                    model: model.clone(),
+                    request: messages,
+                    response: content.clone(),
+                    usage: res.usage,
+                });
+                // save after every call so an interrupted run keeps what it recorded
+                state.cassette.save(&self.path)?;
+                (content, res.usage)
+            }
+            _ => {
+                let (content, usage) = self.replay_response(&key)?;
+                on_token(&content);
+                (content, usage)
+            }
+        };
+        Ok(APIResponse { choices: vec![APIChoice { message: APIMessage { content } }], model: Some(model), usage })
+    }
+}
+
+// Wraps the configured provider when LLM_CASSETTE is set. Replay never touches the network,
+// so it does not need any API keys.
+pub fn cassette_from_env(
+    config: &ProviderConfig,
+    inner: impl FnOnce() -> BotResult<Box<dyn LlmProvider>>,
+) -> Option<BotResult<Box<dyn LlmProvider>>> {
+    let path = PathBuf::from(env::var("LLM_CASSETTE").ok()?);
+    let provider = || -> BotResult<Box<dyn LlmProvider>> {
+        let mode = match env::var("LLM_CASSETTE_MODE") {
+            Ok(name) => CassetteMode::from_name(&name).ok_or(BotError::Config(format!(
+                "LLM_CASSETTE_MODE must be record or replay (got {})",
+                name
+            )))?,
+            Err(_) => CassetteMode::Replay,
+        };
+        Ok(match mode {
+            CassetteMode::Record => Box::new(CassetteProvider::record(path, config.clone(), inner()?)?),
+            CassetteMode::Replay => Box::new(CassetteProvider::replay(path, config.clone())?),
+        })
+    };
+    Some(provider())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::apis::llm_provider::ProviderKind;
+
+    fn config(model: &str) -> ProviderConfig {
+        ProviderConfig { kind: ProviderKind::OpenAi, model: model.to_string(), base_url: String::new(), temperature: 0.1, stream: false, structured: false }
+    }
+
+    struct CountingProvider;
+
+    #[async_trait]
+    impl LlmProvider for CountingProvider {
+        fn model(&self) -> &str {
+            "test-model"
+        }
+        async fn complete(&self, messages: Vec<Message>, _settings: &ModelSettings) -> BotResult<APIResponse> {
+            let content = format!("echo: {}", messages[0].content);
+            let usage = Some(Usage { prompt_tokens: 10, completion_tokens: 5 });
+            Ok(APIResponse { choices: vec![APIChoice { message: APIMessage { content } }], model: None, usage })
+        }
+    }
+
+    fn msg(content: &str) -> Vec<Message> {
+        vec![Message { role: "system".to_string(), content: content.to_string() }]
+    }
+
+    struct EmptyProvider;
+
+    #[async_trait]
+    impl LlmProvider for EmptyProvider {
+        fn model(&self) -> &str {
+            "test-model"
+        }
+        async fn complete(&self, _messages: Vec<Message>, _settings: &ModelSettings) -> BotResult<APIResponse> {
+            Ok(APIResponse { choices: vec![], model: None, usage: None })
+        }
+    }
+
+    #[test]
+    fn test_request_key_is_stable() {
+        assert_eq!(request_key("gpt-4", &msg("hello"), None), request_key("gpt-4", &msg("hello"), None));
+        assert_ne!(request_key("gpt-4", &msg("hello"), None), request_key("gpt-3.5-turbo", &msg("hello"), None));
+        assert_ne!(request_key("gpt-4", &msg("hello"), None), request_key("gpt-4", &msg("hello!"), None));
+        let schema = OutputSchema::of::<Vec<String>>("print_site_urls");
+        assert_ne!(request_key("gpt-4", &msg("hello"), None), request_key("gpt-4", &msg("hello"), Some(&schema)));
+    }
+
+    #[tokio::test]
+    async fn test_record_then_replay() {
+        let path = env::temp_dir().join(format!("hannah_cassette_{}.json", std::process::id()));
+        let _ = fs::remove_file(&path);
+
+        let recorder = CassetteProvider::record(path.clone(), config("test-model"), Box::new(CountingProvider)).unwrap();
+        let recorded = recorder.complete(msg("build a todo app"), &ModelSettings::default()).await.unwrap();
+        assert_eq!(recorded.choices[0].message.content, "echo: build a todo app");
+
+        let player = CassetteProvider::replay(path.clone(), config("test-model")).unwrap();
+        let replayed = player.complete(msg("build a todo app"), &ModelSettings::default()).await.unwrap();
+        assert_eq!(replayed.choices[0].message.content, "echo: build a todo app");
+        assert_eq!(replayed.usage, recorded.usage);
+        assert!(player.complete(msg("something never recorded"), &ModelSettings::default()).await.is_err());
+
+        let recorder = CassetteProvider::record(path.clone(), config("test-model"), Box::new(EmptyProvider)).unwrap();
+        assert!(matches!(recorder.complete(msg("hello"), &ModelSettings::default()).await, Err(BotError::Transport(_))));
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_routed_provider_replays_under_its_own_model() {
+        let path = env::temp_dir().join(format!("hannah_cassette_routed_{}.json", std::process::id()));
+        let _ = fs::remove_file(&path);
+        let routed = ModelSettings { provider: Some("anthropic".to_string()), ..ModelSettings::default() };
+
+        let recorder = CassetteProvider::record(path.clone(), config("gpt-4o"), Box::new(CountingProvider)).unwrap();
+        recorder.complete(msg("plan the project"), &routed).await.unwrap();
+        let cassette = Cassette::load(&path).unwrap();
+        assert_eq!(cassette.interactions[0].model, "claude-3-5-sonnet-20241022");
+        assert_eq!(cassette.provider.as_deref(), Some("openai"));
+
+        let player = CassetteProvider::replay(path.clone(), config("gpt-4o")).unwrap();
+        let replayed = player.complete(msg("plan the project"), &routed).await.unwrap();
+        assert_eq!(replayed.choices[0].message.content, "echo: plan the project");
+        assert!(player.complete(msg("plan the project"), &ModelSettings::default()).await.is_err());
+
+        fs::remove_file(&path).unwrap();
+    }
+}
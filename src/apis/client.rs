@@ -0,0 +1,157 @@
+use reqwest::{Certificate, Client, Proxy, RequestBuilder, Response};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use super::{
+    llm_provider::{provider_from_env, LlmProvider},
+    retry::{is_stream_interruption, next_chunk, send_with_retry, RateLimiter, RetryPolicy},
+    routing::ModelsConfig,
+};
+use crate::errors::{BotError, BotResult};
+
+// The "http" section of the config file. Proxies from HTTP_PROXY / HTTPS_PROXY are used as well.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(default)]
+pub struct HttpConfig {
+    pub proxy: Option<String>,
+    // extra root certificate (PEM), e.g. for a corporate proxy
+    pub ca_cert: Option<PathBuf>,
+    pub accept_invalid_certs: bool,
+    pub connect_timeout_ms: Option<u64>,
+    pub pool_max_idle_per_host: Option<usize>,
+    pub pool_idle_timeout_secs: Option<u64>,
+}
+
+impl HttpConfig {
+    pub fn build_client(&self) -> BotResult<Client> {
+        let mut builder = Client::builder();
+        if let Some(proxy) = &self.proxy {
+            builder = builder.proxy(Proxy::all(proxy).map_err(|e| BotError::Config(format!("invalid proxy {}: {}", proxy, e)))?);
+        }
+        if let Some(path) = &self.ca_cert {
+            let pem = fs::read(path)?;
+            let cert = Certificate::from_pem(&pem)
+                .map_err(|e| BotError::Config(format!("invalid certificate {}: {}", path.display(), e)))?;
+            builder = builder.add_root_certificate(cert);
+        }
+        if self.accept_invalid_certs {
+            builder = builder.danger_accept_invalid_certs(true);
+        }
+        if let Some(ms) = self.connect_timeout_ms {
+            builder = builder.connect_timeout(Duration::from_millis(ms));
+        }
+        if let Some(max) = self.pool_max_idle_per_host {
+            builder = builder.pool_max_idle_per_host(max);
+        }
+        if let Some(secs) = self.pool_idle_timeout_secs {
+            builder = builder.pool_idle_timeout(Duration::from_secs(secs));
+        }
+        Ok(builder.build()?)
+    }
+}
+
+// One connection pool plus the retry policy and rate limiter every LLM request goes through.
+// Cloning is cheap and shares the pool.
+#[derive(Debug, Clone)]
+pub struct HttpTransport {
+    pub client: Client,
+    pub retry: RetryPolicy,
+    pub limiter: Option<Arc<RateLimiter>>,
+}
+
+impl HttpTransport {
+    pub fn new(client: Client, retry: RetryPolicy) -> Self {
+        let limiter = retry.requests_per_minute.map(|rpm| Arc::new(RateLimiter::new(rpm, retry.burst)));
+        Self { client, retry, limiter }
+    }
+    pub async fn send(&self, build: impl Fn(&Client) -> RequestBuilder) -> BotResult<Response> {
+        let timeout = self.retry.request_timeout();
+        send_with_retry(|| build(&self.client).timeout(timeout), &self.retry, self.limiter.as_deref()).await
+    }
+    // For a streamed answer: read it with `next_chunk`, which bounds the gaps between chunks instead
+    pub async fn send_stream(&self, build: impl Fn(&Client) -> RequestBuilder) -> BotResult<Response> {
+        send_with_retry(|| build(&self.client), &self.retry, self.limiter.as_deref()).await
+    }
+    pub async fn next_chunk(&self, res: &mut Response) -> BotResult<Option<Vec<u8>>> {
+        next_chunk(res, &self.retry).await
+    }
+    // Whether a stream that failed on attempt `attempt` should be requested again; waits the
+    // backoff first if so
+    pub async fn retry_stream(&self, e: &BotError, attempt: u32) -> bool {
+        if !is_stream_interruption(e) || attempt >= self.retry.max_attempts {
+            return false;
+        }
+        tokio::time::sleep(self.retry.backoff(attempt)).await;
+        true
+    }
+}
+
+// Created once per run: the HTTP transport, the LLM provider with its credentials and base url,
+// and which model each agent and AI function uses. Shared by the manager and every agent.
+pub struct ClientContext {
+    pub transport: HttpTransport,
+    pub provider: Box<dyn LlmProvider>,
+    pub models: ModelsConfig,
+}
+
+impl ClientContext {
+    pub fn new(http: &HttpConfig, retry: RetryPolicy, models: ModelsConfig) -> BotResult<Self> {
+        models.validate()?;
+        let transport = HttpTransport::new(http.build_client()?, retry);
+        let provider = provider_from_env(&transport)?;
+        Ok(Self::with_provider(transport, provider, models))
+    }
+    pub fn with_provider(transport: HttpTransport, provider: Box<dyn LlmProvider>, models: ModelsConfig) -> Self {
+        Self { transport, provider, models }
+    }
+    pub fn http(&self) -> &Client {
+        &self.transport.client
+    }
+}
+
+static CONTEXT: Mutex<Option<Arc<ClientContext>>> = Mutex::new(None);
+
+// Call after the LLM_* environment is final; the provider is read from it once
+pub fn init_client_context(http: &HttpConfig, retry: RetryPolicy, models: ModelsConfig) -> BotResult<()> {
+    set_client_context(ClientContext::new(http, retry, models)?);
+    Ok(())
+}
+
+pub fn set_client_context(context: ClientContext) {
+    *CONTEXT.lock().unwrap_or_else(|e| e.into_inner()) = Some(Arc::new(context));
+}
+
+// The shared context, built with default settings on first use if nobody set one up
+pub fn client_context() -> BotResult<Arc<ClientContext>> {
+    let mut guard = CONTEXT.lock().unwrap_or_else(|e| e.into_inner());
+    if let Some(context) = guard.as_ref() {
+        return Ok(context.clone());
+    }
+    let context = Arc::new(ClientContext::new(&HttpConfig::default(), RetryPolicy::default(), ModelsConfig::default())?);
+    *guard = Some(context.clone());
+    Ok(context)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_http_config_builds_client() {
+        let config = HttpConfig {
+            proxy: Some("http://127.0.0.1:3128".to_string()),
+            connect_timeout_ms: Some(500),
+            pool_max_idle_per_host: Some(4),
+            ..Default::default()
+        };
+        assert!(config.build_client().is_ok());
+
+        let bad_proxy = HttpConfig { proxy: Some("not a url".to_string()), ..Default::default() };
+        assert!(matches!(bad_proxy.build_client(), Err(BotError::Config(_))));
+    }
+}
@@ -0,0 +1,669 @@
+use super::{cassette::cassette_from_env, client::HttpTransport, routing::{ModelSettings, RoutingProvider}, sse::SseParser};
+use crate::models::general::{
+    llm::{
+        APIChoice, APIMessage, APIResponse, AnthropicRequest, AnthropicResponse, AnthropicStreamEvent, AnthropicTool,
+        ChatCompletion, Message, StreamChunk, Usage,
+    },
+    schema::OutputSchema,
+};
+use crate::errors::{BotError, BotResult};
+use async_trait::async_trait;
+use reqwest::{header::{HeaderMap, HeaderValue}, Response};
+use std::{
+    env,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+const OPENAI_BASE_URL: &str = "https://api.openai.com/v1";
+const ANTHROPIC_BASE_URL: &str = "https://api.anthropic.com/v1";
+const ANTHROPIC_VERSION: &str = "2023-06-01";
+const LOCAL_BASE_URL: &str = "http://localhost:11434/v1";
+
+// Receives completion text as it streams in
+pub type TokenSink<'a> = dyn Fn(&str) + Send + Sync + 'a;
+// Sent to the token sink before an interrupted stream is requested again, so the tokens already
+// shown are not read as the start of the new answer
+pub const STREAM_RESTARTED: &str = "\n[connection lost, the answer starts again]\n";
+
+// Anything that can turn a conversation into a chat completion
+#[async_trait]
+pub trait LlmProvider: Send + Sync {
+    fn model(&self) -> &str;
+    // The model a call with these settings goes to
+    fn model_for(&self, settings: &ModelSettings) -> String {
+        settings.model.clone().unwrap_or(self.model().to_string())
+    }
+    async fn complete(&self, messages: Vec<Message>, settings: &ModelSettings) -> BotResult<APIResponse>;
+    // Passes text to `on_token` as it arrives and returns the assembled response. Providers that
+    // cannot stream hand over the whole answer at once.
+    async fn complete_streaming(&self, messages: Vec<Message>, settings: &ModelSettings, on_token: &TokenSink<'_>) -> BotResult<APIResponse> {
+        let res = self.complete(messages, settings).await?;
+        Ok(deliver_whole(res, on_token))
+    }
+    // Asks for output matching `schema` through the provider's native structured output. Providers
+    // without it rely on the prompt describing the JSON.
+    async fn complete_structured(
+        &self,
+        messages: Vec<Message>,
+        _schema: &OutputSchema,
+        settings: &ModelSettings,
+        on_token: &TokenSink<'_>,
+    ) -> BotResult<APIResponse> {
+        self.complete_streaming(messages, settings, on_token).await
+    }
+}
+
+fn deliver_whole(res: APIResponse, on_token: &TokenSink<'_>) -> APIResponse {
+    if let Some(choice) = res.choices.first() {
+        on_token(&choice.message.content);
+    }
+    res
+}
+
+fn unwrapped(mut res: APIResponse, schema: &OutputSchema) -> APIResponse {
+    for choice in res.choices.iter_mut() {
+        choice.message.content = schema.unwrap_output(std::mem::take(&mut choice.message.content));
+    }
+    res
+}
+
+fn assembled(content: String, model: Option<String>, usage: Option<Usage>) -> APIResponse {
+    APIResponse { choices: vec![APIChoice { message: APIMessage { content } }], model, usage }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ProviderKind {
+    OpenAi,
+    Anthropic,
+    // OpenAI-compatible local servers: Ollama, llama.cpp, vLLM
+    Local,
+}
+
+impl ProviderKind {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.trim().to_lowercase().as_str() {
+            "openai" => Some(Self::OpenAi),
+            "anthropic" => Some(Self::Anthropic),
+            "local" | "ollama" | "llamacpp" | "llama.cpp" | "vllm" => Some(Self::Local),
+            _ => None,
+        }
+    }
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::OpenAi => "openai",
+            Self::Anthropic => "anthropic",
+            Self::Local => "local",
+        }
+    }
+    fn default_model(&self) -> &'static str {
+        match self {
+            Self::OpenAi => "gpt-4o",
+            Self::Anthropic => "claude-3-5-sonnet-20241022",
+            Self::Local => "llama3",
+        }
+    }
+    fn default_base_url(&self) -> &'static str {
+        match self {
+            Self::OpenAi => OPENAI_BASE_URL,
+            Self::Anthropic => ANTHROPIC_BASE_URL,
+            Self::Local => LOCAL_BASE_URL,
+        }
+    }
+}
+
+// Provider selection, read from the environment:
+//   LLM_PROVIDER = openai (default) | anthropic | local
+//   LLM_MODEL, LLM_BASE_URL, LLM_TEMPERATURE override the provider defaults
+//   LLM_STREAM = 0 turns off streaming
+//   LLM_STRUCTURED = 0 / 1 turns schema-checked output off or on. Unless asked for it is only on
+//   for Anthropic and for OpenAI models known to support json_schema, never for local servers
+#[derive(Debug, Clone)]
+pub struct ProviderConfig {
+    pub kind: ProviderKind,
+    pub model: String,
+    pub base_url: String,
+    pub temperature: f32,
+    pub stream: bool,
+    pub structured: bool,
+}
+
+impl ProviderConfig {
+    pub fn from_env() -> BotResult<Self> {
+        let kind = match env::var("LLM_PROVIDER") {
+            Ok(name) => ProviderKind::from_name(&name).ok_or(BotError::Config(format!(
+                "LLM_PROVIDER must be one of openai, anthropic, local (got {})",
+                name
+            )))?,
+            Err(_) => ProviderKind::OpenAi,
+        };
+        let model = env::var("LLM_MODEL").unwrap_or(kind.default_model().to_string());
+        let base_url = env::var("LLM_BASE_URL").unwrap_or(kind.default_base_url().to_string());
+        let temperature = env::var("LLM_TEMPERATURE")
+            .ok()
+            .and_then(|t| t.parse::<f32>().ok())
+            .unwrap_or(0.1);
+        let stream = !matches!(env::var("LLM_STREAM").as_deref(), Ok("0") | Ok("false") | Ok("off"));
+        let structured = structured_from_env(kind, &model);
+        Ok(Self { kind, model, base_url: base_url.trim_end_matches('/').to_string(), temperature, stream, structured })
+    }
+    // Another provider picked in the "models" config: its own defaults, the environment's sampling
+    pub fn for_kind(kind: ProviderKind, base: &ProviderConfig) -> Self {
+        Self {
+            kind,
+            model: kind.default_model().to_string(),
+            base_url: kind.default_base_url().to_string(),
+            temperature: base.temperature,
+            stream: base.stream,
+            structured: structured_from_env(kind, kind.default_model()),
+        }
+    }
+}
+
+// OpenAI models that accept response_format json_schema; older ones answer it with a 400
+fn supports_json_schema(model: &str) -> bool {
+    ["gpt-4o", "gpt-4.1", "gpt-5", "o1", "o3", "o4"].iter().any(|prefix| model.starts_with(prefix))
+}
+
+fn structured_from_env(kind: ProviderKind, model: &str) -> bool {
+    match env::var("LLM_STRUCTURED").as_deref() {
+        Ok("0") | Ok("false") | Ok("off") => false,
+        Ok("1") | Ok("true") | Ok("on") => true,
+        _ => match kind {
+            ProviderKind::OpenAi => supports_json_schema(model),
+            ProviderKind::Anthropic => true,
+            ProviderKind::Local => false,
+        },
+    }
+}
+
+// A 400 about the response_format itself, as opposed to anything else wrong with the request
+fn is_response_format_rejection(e: &BotError) -> bool {
+    matches!(e, BotError::Transport(msg) if msg.starts_with("400") && (msg.contains("response_format") || msg.contains("json_schema")))
+}
+
+fn required_env(name: &str) -> BotResult<String> {
+    env::var(name).map_err(|_| BotError::Config(format!("{} not found", name)))
+}
+
+pub fn provider_from_config(config: ProviderConfig, transport: &HttpTransport) -> BotResult<Box<dyn LlmProvider>> {
+    let transport = transport.clone();
+    let provider: Box<dyn LlmProvider> = match config.kind {
+        ProviderKind::OpenAi => {
+            let api_key = required_env("OPENAI_API_KEY")?;
+            let org_id = env::var("ORG_ID").ok();
+            Box::new(OpenAiProvider::new(config, Some(api_key), org_id, transport)?)
+        }
+        ProviderKind::Anthropic => {
+            let api_key = required_env("ANTHROPIC_API_KEY")?;
+            Box::new(AnthropicProvider::new(config, api_key, transport)?)
+        }
+        ProviderKind::Local => {
+            // Most local servers ignore auth, but vLLM can be started with --api-key
+            let api_key = env::var("LLM_API_KEY").ok();
+            Box::new(OpenAiProvider::new(config, api_key, None, transport)?)
+        }
+    };
+    Ok(provider)
+}
+
+pub fn provider_from_env(transport: &HttpTransport) -> BotResult<Box<dyn LlmProvider>> {
+    let config = ProviderConfig::from_env()?;
+    let routing = || -> BotResult<Box<dyn LlmProvider>> { Ok(Box::new(RoutingProvider::new(config.clone(), transport)?)) };
+    match cassette_from_env(&config, routing) {
+        Some(provider) => provider,
+        None => routing(),
+    }
+}
+
+// OpenAI chat completions, also used for OpenAI-compatible local servers
+pub struct OpenAiProvider {
+    config: ProviderConfig,
+    // credentials, sent with every request
+    headers: HeaderMap,
+    transport: HttpTransport,
+    // set once the server refused a response_format, later calls go without it
+    structured_rejected: AtomicBool,
+}
+
+impl OpenAiProvider {
+    pub fn new(config: ProviderConfig, api_key: Option<String>, org_id: Option<String>, transport: HttpTransport) -> BotResult<Self> {
+        // establish the headers
+        let mut headers = HeaderMap::new();
+        if let Some(api_key) = &api_key {
+            headers.insert("authorization",  HeaderValue::from_str(&format!("Bearer {}", api_key))?);
+        }
+        if let Some(org_id) = &org_id {
+            headers.insert("OpenAI-Organization",  HeaderValue::from_str(org_id.as_str())?);
+        }
+        Ok(Self { config, headers, transport, structured_rejected: AtomicBool::new(false) })
+    }
+    fn chat_completion(&self, messages: Vec<Message>, schema: Option<&OutputSchema>, settings: &ModelSettings) -> ChatCompletion {
+        let stream = self.config.stream;
+        ChatCompletion {
+            model: self.model_for(settings),
+            messages,
+            temperature: settings.temperature.unwrap_or(self.config.temperature),
+            stream: if stream { Some(true) } else { None },
+            // local servers do not all understand stream_options
+            stream_options: if stream && self.config.kind == ProviderKind::OpenAi {
+                Some(serde_json::json!({ "include_usage": true }))
+            } else {
+                None
+            },
+            response_format: schema.map(|schema| {
+                serde_json::json!({
+                    "type": "json_schema",
+                    "json_schema": { "name": schema.name, "schema": schema.schema, "strict": schema.strict },
+                })
+            }),
+            max_tokens: settings.max_tokens,
+            top_p: settings.top_p,
+            stop: settings.stop.clone(),
+        }
+    }
+    async fn send(&self, chat_completion: &ChatCompletion) -> BotResult<APIResponse> {
+        let url = format!("{}/chat/completions", self.config.base_url);
+        let res = self.transport.send(|client| client.post(&url).headers(self.headers.clone()).json(chat_completion)).await?;
+        let res = res.json::<APIResponse>().await?;
+        Ok(res)
+    }
+    async fn send_streaming(&self, chat_completion: &ChatCompletion, on_token: &TokenSink<'_>) -> BotResult<APIResponse> {
+        if chat_completion.stream != Some(true) {
+            let res = self.send(chat_completion).await?;
+            return Ok(deliver_whole(res, on_token));
+        }
+        let url = format!("{}/chat/completions", self.config.base_url);
+        let mut attempt = 1;
+        loop {
+            let res = self.transport.send_stream(|client| client.post(&url).headers(self.headers.clone()).json(chat_completion)).await?;
+            match self.read_stream(res, on_token).await {
+                Err(e) if self.transport.retry_stream(&e, attempt).await => {
+                    on_token(STREAM_RESTARTED);
+                    attempt += 1
+                }
+                res => return res,
+            }
+        }
+    }
+    async fn read_stream(&self, mut res: Response, on_token: &TokenSink<'_>) -> BotResult<APIResponse> {
+        let mut parser = SseParser::default();
+        let (mut content, mut model, mut usage) = (String::new(), None, None);
+        let mut handle = |data: &str| -> BotResult<()> {
+            if data == "[DONE]" {
+                return Ok(());
+            }
+            let chunk: StreamChunk = serde_json::from_str(data)
+                .map_err(|e| BotError::Transport(format!("unexpected stream chunk {}: {}", data, e)))?;
+            for token in chunk.choices.into_iter().filter_map(|c| c.delta.content) {
+                on_token(&token);
+                content.push_str(&token);
+            }
+            model = chunk.model.or(model.take());
+            usage = chunk.usage.or(usage);
+            Ok(())
+        };
+        while let Some(bytes) = self.transport.next_chunk(&mut res).await? {
+            for event in parser.push(&bytes) {
+                handle(&event.data)?;
+            }
+        }
+        if let Some(event) = parser.finish() {
+            handle(&event.data)?;
+        }
+        Ok(assembled(content, model, usage))
+    }
+}
+
+#[async_trait]
+impl LlmProvider for OpenAiProvider {
+    fn model(&self) -> &str {
+        &self.config.model
+    }
+    async fn complete(&self, messages: Vec<Message>, settings: &ModelSettings) -> BotResult<APIResponse> {
+        let mut chat_completion = self.chat_completion(messages, None, settings);
+        chat_completion.stream = None;
+        chat_completion.stream_options = None;
+        self.send(&chat_completion).await
+    }
+    async fn complete_streaming(&self, messages: Vec<Message>, settings: &ModelSettings, on_token: &TokenSink<'_>) -> BotResult<APIResponse> {
+        self.send_streaming(&self.chat_completion(messages, None, settings), on_token).await
+    }
+    async fn complete_structured(
+        &self,
+        messages: Vec<Message>,
+        schema: &OutputSchema,
+        settings: &ModelSettings,
+        on_token: &TokenSink<'_>,
+    ) -> BotResult<APIResponse> {
+        if !self.config.structured || self.structured_rejected.load(Ordering::Relaxed) {
+            return self.complete_streaming(messages, settings, on_token).await;
+        }
+        match self.send_streaming(&self.chat_completion(messages.clone(), Some(schema), settings), on_token).await {
+            Ok(res) => Ok(unwrapped(res, schema)),
+            // the prompt describes the JSON as well, so decoding still works without it
+            Err(e) if is_response_format_rejection(&e) => {
+                self.structured_rejected.store(true, Ordering::Relaxed);
+                self.complete_streaming(messages, settings, on_token).await
+            }
+            Err(e) => Err(e),
+        }
+    }
+}
+
+// Anthropic Messages API
+pub struct AnthropicProvider {
+    config: ProviderConfig,
+    headers: HeaderMap,
+    transport: HttpTransport,
+    max_tokens: u32,
+}
+
+impl AnthropicProvider {
+    pub fn new(config: ProviderConfig, api_key: String, transport: HttpTransport) -> BotResult<Self> {
+        let mut headers = HeaderMap::new();
+        headers.insert("x-api-key",  HeaderValue::from_str(&api_key)?);
+        headers.insert("anthropic-version",  HeaderValue::from_static(ANTHROPIC_VERSION));
+        Ok(Self { config, headers, transport, max_tokens: 4096 })
+    }
+}
+
+// Anthropic takes system prompts as a top level field and needs at least one user turn.
+// Our ai functions only send a system message, so that becomes the user turn.
+pub fn to_anthropic_request(model: &str, max_tokens: u32, temperature: f32, messages: Vec<Message>) -> AnthropicRequest {
+    let (system_msgs, mut chat_msgs): (Vec<Message>, Vec<Message>) =
+        messages.into_iter().partition(|m| m.role == "system");
+    let system = system_msgs.iter().map(|m| m.content.as_str()).collect::<Vec<&str>>().join("\n");
+    let system = if chat_msgs.is_empty() {
+        chat_msgs.push(Message { role: "user".to_string(), content: system });
+        None
+    } else if system.is_empty() {
+        None
+    } else {
+        Some(system)
+    };
+    AnthropicRequest {
+        model: model.to_string(),
+        max_tokens,
+        system,
+        messages: chat_msgs,
+        temperature,
+        top_p: None,
+        stop_sequences: None,
+        stream: None,
+        tools: None,
+        tool_choice: None,
+    }
+}
+
+impl AnthropicProvider {
+    fn request(&self, messages: Vec<Message>, schema: Option<&OutputSchema>, settings: &ModelSettings) -> AnthropicRequest {
+        let mut request = to_anthropic_request(
+            &self.model_for(settings),
+            settings.max_tokens.unwrap_or(self.max_tokens),
+            settings.temperature.unwrap_or(self.config.temperature),
+            messages,
+        );
+        request.top_p = settings.top_p;
+        request.stop_sequences = settings.stop.clone();
+        request.stream = if self.config.stream { Some(true) } else { None };
+        if let Some(schema) = schema {
+            request.tools = Some(vec![AnthropicTool {
+                name: schema.name.clone(),
+                description: format!("Records the output of {}", schema.name),
+                input_schema: schema.schema.clone(),
+            }]);
+            request.tool_choice = Some(serde_json::json!({ "type": "tool", "name": schema.name }));
+        }
+        request
+    }
+    async fn send(&self, request: &AnthropicRequest) -> BotResult<APIResponse> {
+        let url = format!("{}/messages", self.config.base_url);
+        let res = self.transport.send(|client| client.post(&url).headers(self.headers.clone()).json(request)).await?;
+        let res = res.json::<AnthropicResponse>().await?;
+        // map onto the OpenAI shape the rest of the crate reads; a forced tool call answers with its input
+        let content = res.content.into_iter()
+            .filter_map(|c| match c.content_type.as_str() {
+                "text" => Some(c.text),
+                "tool_use" => c.input.map(|input| input.to_string()),
+                _ => None,
+            })
+            .collect::<Vec<String>>()
+            .join("");
+        let usage = res.usage.map(|u| Usage { prompt_tokens: u.input_tokens, completion_tokens: u.output_tokens });
+        Ok(assembled(content, res.model, usage))
+    }
+    async fn send_streaming(&self, request: &AnthropicRequest, on_token: &TokenSink<'_>) -> BotResult<APIResponse> {
+        if request.stream != Some(true) {
+            let res = self.send(request).await?;
+            return Ok(deliver_whole(res, on_token));
+        }
+        let url = format!("{}/messages", self.config.base_url);
+        let mut attempt = 1;
+        loop {
+            let res = self.transport.send_stream(|client| client.post(&url).headers(self.headers.clone()).json(request)).await?;
+            match self.read_stream(res, on_token).await {
+                Err(e) if self.transport.retry_stream(&e, attempt).await => {
+                    on_token(STREAM_RESTARTED);
+                    attempt += 1
+                }
+                res => return res,
+            }
+        }
+    }
+    async fn read_stream(&self, mut res: Response, on_token: &TokenSink<'_>) -> BotResult<APIResponse> {
+        let mut parser = SseParser::default();
+        let (mut content, mut model, mut usage) = (String::new(), None, Usage::default());
+        let mut handle = |data: &str| -> BotResult<()> {
+            let event: AnthropicStreamEvent = serde_json::from_str(data)
+                .map_err(|e| BotError::Transport(format!("unexpected stream event {}: {}", data, e)))?;
+            match event.event_type.as_str() {
+                "message_start" => {
+                    if let Some(message) = event.message {
+                        model = message.model;
+                        if let Some(u) = message.usage {
+                            usage.prompt_tokens = u.input_tokens;
+                        }
+                    }
+                }
+                "content_block_delta" => {
+                    if let Some(text) = event.delta.and_then(|d| d.text.or(d.partial_json)) {
+                        on_token(&text);
+                        content.push_str(&text);
+                    }
+                }
+                "message_delta" => {
+                    if let Some(u) = event.usage {
+                        usage.completion_tokens = u.output_tokens;
+                    }
+                }
+                "error" => {
+                    return Err(BotError::Transport(format!("stream error: {}", event.error.unwrap_or_default())));
+                }
+                _ => {}
+            }
+            Ok(())
+        };
+        while let Some(bytes) = self.transport.next_chunk(&mut res).await? {
+            for event in parser.push(&bytes) {
+                handle(&event.data)?;
+            }
+        }
+        if let Some(event) = parser.finish() {
+            handle(&event.data)?;
+        }
+        Ok(assembled(content, model, Some(usage)))
+    }
+}
+
+#[async_trait]
+impl LlmProvider for AnthropicProvider {
+    fn model(&self) -> &str {
+        &self.config.model
+    }
+    async fn complete(&self, messages: Vec<Message>, settings: &ModelSettings) -> BotResult<APIResponse> {
+        let mut request = self.request(messages, None, settings);
+        request.stream = None;
+        self.send(&request).await
+    }
+    async fn complete_streaming(&self, messages: Vec<Message>, settings: &ModelSettings, on_token: &TokenSink<'_>) -> BotResult<APIResponse> {
+        self.send_streaming(&self.request(messages, None, settings), on_token).await
+    }
+    async fn complete_structured(
+        &self,
+        messages: Vec<Message>,
+        schema: &OutputSchema,
+        settings: &ModelSettings,
+        on_token: &TokenSink<'_>,
+    ) -> BotResult<APIResponse> {
+        if !self.config.structured {
+            return self.complete_streaming(messages, settings, on_token).await;
+        }
+        let res = self.send_streaming(&self.request(messages, Some(schema), settings), on_token).await?;
+        Ok(unwrapped(res, schema))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::apis::retry::{stub_server::{http_response, start}, RetryPolicy};
+
+    fn test_transport() -> HttpTransport {
+        HttpTransport::new(reqwest::Client::new(), RetryPolicy { initial_backoff_ms: 10, ..Default::default() })
+    }
+
+    #[test]
+    fn test_anthropic_request_moves_lone_system_prompt_to_user_turn() {
+        let messages = vec![Message { role: "system".to_string(), content: "FUNCTION ...".to_string() }];
+        let req = to_anthropic_request("claude", 100, 0.1, messages);
+        assert!(req.system.is_none());
+        assert_eq!(req.messages.len(), 1);
+        assert_eq!(req.messages[0].role, "user");
+        assert_eq!(req.messages[0].content, "FUNCTION ...");
+    }
+
+    #[test]
+    fn test_provider_kind_from_name() {
+        assert_eq!(ProviderKind::from_name("Anthropic"), Some(ProviderKind::Anthropic));
+        assert_eq!(ProviderKind::from_name("ollama"), Some(ProviderKind::Local));
+        assert_eq!(ProviderKind::from_name("vllm"), Some(ProviderKind::Local));
+        assert_eq!(ProviderKind::from_name("gemini"), None);
+    }
+
+    #[tokio::test]
+    async fn test_openai_provider_retries_rate_limited_call() {
+        let body = r#"{"model":"gpt-4-0613","choices":[{"message":{"role":"assistant","content":"hello"}}],"usage":{"prompt_tokens":12,"completion_tokens":3,"total_tokens":15}}"#;
+        let (base_url, _) = start(vec![
+            http_response("429 Too Many Requests", &[("Retry-After", "0")], "{}"),
+            http_response("200 OK", &[("Content-Type", "application/json")], body),
+        ])
+        .await;
+        let config = ProviderConfig { kind: ProviderKind::Local, model: "gpt-4".to_string(), base_url, temperature: 0.1, stream: false, structured: false };
+        let provider = OpenAiProvider::new(config, None, None, test_transport()).unwrap();
+        let messages = vec![Message { role: "system".to_string(), content: "hi".to_string() }];
+        let res = provider.complete(messages, &ModelSettings::default()).await.unwrap();
+        assert_eq!(res.choices[0].message.content, "hello");
+        assert_eq!(res.usage.unwrap().completion_tokens, 3);
+    }
+
+    #[tokio::test]
+    async fn test_openai_provider_falls_back_when_response_format_is_rejected() {
+        let error = r#"{"error":{"message":"Invalid parameter: 'response_format' of type 'json_schema' is not supported with this model.","type":"invalid_request_error"}}"#;
+        let body = r#"{"model":"gpt-4-0613","choices":[{"message":{"role":"assistant","content":"[\"https://api.example.com\"]"}}]}"#;
+        let (base_url, hits) = start(vec![
+            http_response("400 Bad Request", &[("Content-Type", "application/json")], error),
+            http_response("200 OK", &[("Content-Type", "application/json")], body),
+        ])
+        .await;
+        let config = ProviderConfig { kind: ProviderKind::OpenAi, model: "gpt-4".to_string(), base_url, temperature: 0.1, stream: false, structured: true };
+        let provider = OpenAiProvider::new(config, None, None, test_transport()).unwrap();
+        let schema = OutputSchema::of::<Vec<String>>("print_site_urls");
+        let messages = vec![Message { role: "system".to_string(), content: "hi".to_string() }];
+        let res = provider.complete_structured(messages.clone(), &schema, &ModelSettings::default(), &|_: &str| {}).await.unwrap();
+        assert_eq!(res.choices[0].message.content, r#"["https://api.example.com"]"#);
+        assert_eq!(hits.load(std::sync::atomic::Ordering::SeqCst), 2);
+        // later calls do not ask again
+        provider.complete_structured(messages, &schema, &ModelSettings::default(), &|_: &str| {}).await.unwrap();
+        assert_eq!(hits.load(std::sync::atomic::Ordering::SeqCst), 3);
+        assert!(supports_json_schema(ProviderKind::OpenAi.default_model()) && !supports_json_schema("gpt-4"));
+    }
+
+    #[tokio::test]
+    async fn test_openai_provider_streams_tokens() {
+        let body = concat!(
+            "data: {\"model\":\"llama3\",\"choices\":[{\"delta\":{\"role\":\"assistant\"}}]}\n\n",
+            "data: {\"model\":\"llama3\",\"choices\":[{\"delta\":{\"content\":\"fn \"}}]}\n\n",
+            "data: {\"model\":\"llama3\",\"choices\":[{\"delta\":{\"content\":\"main() {}\"}}]}\n\n",
+            "data: {\"choices\":[],\"usage\":{\"prompt_tokens\":7,\"completion_tokens\":4}}\n\n",
+            "data: [DONE]\n\n",
+        );
+        let (base_url, _) = start(vec![http_response("200 OK", &[("Content-Type", "text/event-stream")], body)]).await;
+        let config = ProviderConfig { kind: ProviderKind::Local, model: "llama3".to_string(), base_url, temperature: 0.1, stream: true, structured: false };
+        let provider = OpenAiProvider::new(config, None, None, test_transport()).unwrap();
+        let tokens = std::sync::Mutex::new(vec![]);
+        let messages = vec![Message { role: "system".to_string(), content: "hi".to_string() }];
+        let res = provider
+            .complete_streaming(messages, &ModelSettings::default(), &|token: &str| tokens.lock().unwrap().push(token.to_string()))
+            .await
+            .unwrap();
+        assert_eq!(*tokens.lock().unwrap(), vec!["fn ", "main() {}"]);
+        assert_eq!(res.choices[0].message.content, "fn main() {}");
+        assert_eq!(res.usage, Some(Usage { prompt_tokens: 7, completion_tokens: 4 }));
+    }
+
+    #[tokio::test]
+    async fn test_openai_provider_requests_an_interrupted_stream_again() {
+        let first = "data: {\"choices\":[{\"delta\":{\"content\":\"fn \"}}]}\n\n";
+        // promises more than it sends, then hangs up
+        let cut = format!("HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\nContent-Length: 500\r\nConnection: close\r\n\r\n{}", first);
+        let body = format!("{}data: {{\"choices\":[{{\"delta\":{{\"content\":\"main() {{}}\"}}}}]}}\n\ndata: [DONE]\n\n", first);
+        let (base_url, hits) = start(vec![cut, http_response("200 OK", &[("Content-Type", "text/event-stream")], &body)]).await;
+        let config = ProviderConfig { kind: ProviderKind::Local, model: "llama3".to_string(), base_url, temperature: 0.1, stream: true, structured: false };
+        let provider = OpenAiProvider::new(config, None, None, test_transport()).unwrap();
+        let messages = vec![Message { role: "system".to_string(), content: "hi".to_string() }];
+        let printed = std::sync::Mutex::new(String::new());
+        let res = provider.complete_streaming(messages, &ModelSettings::default(), &|token: &str| printed.lock().unwrap().push_str(token)).await.unwrap();
+        assert_eq!(res.choices[0].message.content, "fn main() {}");
+        assert_eq!(hits.load(std::sync::atomic::Ordering::SeqCst), 2);
+        assert_eq!(*printed.lock().unwrap(), format!("fn {}fn main() {{}}", STREAM_RESTARTED));
+    }
+
+    #[tokio::test]
+    async fn test_anthropic_provider_streams_tokens() {
+        let body = concat!(
+            "event: message_start\ndata: {\"type\":\"message_start\",\"message\":{\"model\":\"claude-3-5-sonnet-20241022\",\"usage\":{\"input_tokens\":9,\"output_tokens\":1}}}\n\n",
+            "event: ping\ndata: {\"type\":\"ping\"}\n\n",
+            "event: content_block_delta\ndata: {\"type\":\"content_block_delta\",\"index\":0,\"delta\":{\"type\":\"text_delta\",\"text\":\"<html>\"}}\n\n",
+            "event: message_delta\ndata: {\"type\":\"message_delta\",\"delta\":{\"stop_reason\":\"end_turn\"},\"usage\":{\"output_tokens\":2}}\n\n",
+            "event: message_stop\ndata: {\"type\":\"message_stop\"}\n\n",
+        );
+        let (base_url, _) = start(vec![http_response("200 OK", &[("Content-Type", "text/event-stream")], body)]).await;
+        let config = ProviderConfig { kind: ProviderKind::Anthropic, model: "claude-3-5-sonnet-20241022".to_string(), base_url, temperature: 0.1, stream: true, structured: false };
+        let provider = AnthropicProvider::new(config, "key".to_string(), test_transport()).unwrap();
+        let messages = vec![Message { role: "system".to_string(), content: "hi".to_string() }];
+        let res = provider.complete_streaming(messages, &ModelSettings::default(), &|_: &str| {}).await.unwrap();
+        assert_eq!(res.choices[0].message.content, "<html>");
+        assert_eq!(res.usage, Some(Usage { prompt_tokens: 9, completion_tokens: 2 }));
+        assert_eq!(res.model.as_deref(), Some("claude-3-5-sonnet-20241022"));
+    }
+
+    #[tokio::test]
+    async fn test_anthropic_provider_structured_output_uses_tool_input() {
+        let body = concat!(
+            "event: message_start\ndata: {\"type\":\"message_start\",\"message\":{\"model\":\"claude-3-5-sonnet-20241022\",\"usage\":{\"input_tokens\":9,\"output_tokens\":1}}}\n\n",
+            "event: content_block_start\ndata: {\"type\":\"content_block_start\",\"index\":0,\"content_block\":{\"type\":\"tool_use\",\"name\":\"print_site_urls\",\"input\":{}}}\n\n",
+            "event: content_block_delta\ndata: {\"type\":\"content_block_delta\",\"index\":0,\"delta\":{\"type\":\"input_json_delta\",\"partial_json\":\"{\\\"value\\\": [\\\"https://\"}}\n\n",
+            "event: content_block_delta\ndata: {\"type\":\"content_block_delta\",\"index\":0,\"delta\":{\"type\":\"input_json_delta\",\"partial_json\":\"api.example.com\\\"]}\"}}\n\n",
+            "event: message_stop\ndata: {\"type\":\"message_stop\"}\n\n",
+        );
+        let (base_url, _) = start(vec![http_response("200 OK", &[("Content-Type", "text/event-stream")], body)]).await;
+        let config = ProviderConfig { kind: ProviderKind::Anthropic, model: "claude-3-5-sonnet-20241022".to_string(), base_url, temperature: 0.1, stream: true, structured: true };
+        let provider = AnthropicProvider::new(config, "key".to_string(), test_transport()).unwrap();
+        let schema = OutputSchema::of::<Vec<String>>("print_site_urls");
+        let request = provider.request(vec![Message { role: "system".to_string(), content: "hi".to_string() }], Some(&schema), &ModelSettings::default());
+        assert_eq!(request.tool_choice, Some(serde_json::json!({ "type": "tool", "name": "print_site_urls" })));
+        let messages = vec![Message { role: "system".to_string(), content: "hi".to_string() }];
+        let res = provider.complete_structured(messages, &schema, &ModelSettings::default(), &|_: &str| {}).await.unwrap();
+        assert_eq!(res.choices[0].message.content, r#"["https://api.example.com"]"#);
+    }
+}
@@ -1 +1,7 @@
-pub mod call_request;
\ No newline at end of file
+pub mod call_request;
+pub mod cassette;
+pub mod client;
+pub mod llm_provider;
+pub mod retry;
+pub mod routing;
+pub mod sse;
\ No newline at end of file
@@ -0,0 +1,326 @@
+use reqwest::{header::RETRY_AFTER, RequestBuilder, Response, StatusCode};
+use serde::{Deserialize, Serialize};
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use crate::errors::{BotError, BotResult};
+
+// The "retry" section of the config file
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct RetryPolicy {
+    pub max_attempts: u32,
+    pub initial_backoff_ms: u64,
+    pub max_backoff_ms: u64,
+    // how long to wait for a whole answer, or for the headers of a streamed one
+    pub request_timeout_ms: u64,
+    // a streamed answer may take as long as it needs, but not go quiet for longer than this
+    pub stream_idle_timeout_ms: u64,
+    // client side limit shared by every agent, unlimited when unset
+    pub requests_per_minute: Option<u32>,
+    pub burst: u32,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 4,
+            initial_backoff_ms: 1000,
+            max_backoff_ms: 30_000,
+            // code generation answers are long, so be patient
+            request_timeout_ms: 180_000,
+            stream_idle_timeout_ms: 60_000,
+            requests_per_minute: None,
+            burst: 1,
+        }
+    }
+}
+
+static JITTER_STATE: AtomicU64 = AtomicU64::new(0);
+
+// xorshift, good enough to keep agents from retrying in lockstep
+fn random_unit() -> f64 {
+    let mut x = JITTER_STATE.load(Ordering::Relaxed);
+    if x == 0 {
+        x = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_nanos() as u64 | 1;
+    }
+    x ^= x << 13;
+    x ^= x >> 7;
+    x ^= x << 17;
+    JITTER_STATE.store(x, Ordering::Relaxed);
+    (x >> 11) as f64 / (1u64 << 53) as f64
+}
+
+impl RetryPolicy {
+    pub fn request_timeout(&self) -> Duration {
+        Duration::from_millis(self.request_timeout_ms)
+    }
+    pub fn stream_idle_timeout(&self) -> Duration {
+        Duration::from_millis(self.stream_idle_timeout_ms)
+    }
+    // Exponential backoff with equal jitter: half of the delay is fixed, half is random
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        let exp = self.initial_backoff_ms.saturating_mul(1u64 << attempt.saturating_sub(1).min(20));
+        let capped = exp.min(self.max_backoff_ms) as f64;
+        Duration::from_millis((capped / 2.0 + capped / 2.0 * random_unit()) as u64)
+    }
+}
+
+fn is_retryable(status: StatusCode) -> bool {
+    status == StatusCode::TOO_MANY_REQUESTS || status == StatusCode::REQUEST_TIMEOUT || status.is_server_error()
+}
+
+// Only the delay-seconds form; providers do not send HTTP dates here. Never longer than the
+// policy's longest backoff, whatever the server asks for.
+fn retry_after(res: &Response, policy: &RetryPolicy) -> Option<Duration> {
+    let value = res.headers().get(RETRY_AFTER)?.to_str().ok()?;
+    let secs: f64 = value.trim().parse().ok().filter(|secs: &f64| secs.is_finite())?;
+    Some(Duration::from_secs_f64(secs.clamp(0.0, policy.max_backoff_ms as f64 / 1000.0)))
+}
+
+// Token bucket holding up to `burst` requests, refilled at `requests_per_minute`
+#[derive(Debug)]
+pub struct RateLimiter {
+    capacity: f64,
+    refill_per_sec: f64,
+    state: Mutex<(f64, Instant)>,
+}
+
+impl RateLimiter {
+    pub fn new(requests_per_minute: u32, burst: u32) -> Self {
+        let capacity = burst.max(1) as f64;
+        Self {
+            capacity,
+            refill_per_sec: requests_per_minute.max(1) as f64 / 60.0,
+            state: Mutex::new((capacity, Instant::now())),
+        }
+    }
+    pub async fn acquire(&self) {
+        loop {
+            let wait = {
+                let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+                let now = Instant::now();
+                let refilled = state.0 + now.duration_since(state.1).as_secs_f64() * self.refill_per_sec;
+                *state = (refilled.min(self.capacity), now);
+                if state.0 >= 1.0 {
+                    state.0 -= 1.0;
+                    return;
+                }
+                Duration::from_secs_f64((1.0 - state.0) / self.refill_per_sec)
+            };
+            tokio::time::sleep(wait).await;
+        }
+    }
+}
+
+// Sends the request made by `build` until it succeeds, fails in a way retrying cannot fix, or
+// runs out of attempts. 429, 408 and 5xx are retried, waiting for Retry-After when given.
+// Only the wait for the response headers is bounded here, so a streamed body is not cut off;
+// callers reading a whole body set a timeout on the request itself.
+pub async fn send_with_retry(
+    build: impl Fn() -> RequestBuilder,
+    policy: &RetryPolicy,
+    limiter: Option<&RateLimiter>,
+) -> BotResult<Response> {
+    let mut attempt = 1;
+    loop {
+        if let Some(limiter) = limiter {
+            limiter.acquire().await;
+        }
+        let sent = tokio::time::timeout(policy.request_timeout(), build().send()).await;
+        let wait = match sent {
+            Ok(Ok(res)) if res.status().is_success() => return Ok(res),
+            Ok(Ok(res)) => {
+                let status = res.status();
+                let delay = retry_after(&res, policy);
+                if !is_retryable(status) || attempt >= policy.max_attempts {
+                    let body: String = res.text().await.unwrap_or_default().chars().take(500).collect();
+                    return Err(BotError::Transport(format!("{} after {} attempt(s): {}", status, attempt, body)));
+                }
+                delay.unwrap_or(policy.backoff(attempt))
+            }
+            Ok(Err(e)) => {
+                let retryable = e.is_timeout() || e.is_connect() || e.is_request();
+                if !retryable || attempt >= policy.max_attempts {
+                    return Err(BotError::Transport(format!("{} after {} attempt(s)", e, attempt)));
+                }
+                policy.backoff(attempt)
+            }
+            Err(_) => {
+                if attempt >= policy.max_attempts {
+                    return Err(BotError::Transport(format!("no response within {:?} after {} attempt(s)", policy.request_timeout(), attempt)));
+                }
+                policy.backoff(attempt)
+            }
+        };
+        tokio::time::sleep(wait).await;
+        attempt += 1;
+    }
+}
+
+const STREAM_INTERRUPTED: &str = "stream interrupted";
+
+// The next piece of a streamed body. A dropped connection or a server that stays quiet for
+// longer than the idle timeout interrupts the stream.
+pub async fn next_chunk(res: &mut Response, policy: &RetryPolicy) -> BotResult<Option<Vec<u8>>> {
+    match tokio::time::timeout(policy.stream_idle_timeout(), res.chunk()).await {
+        Ok(Ok(bytes)) => Ok(bytes.map(|b| b.to_vec())),
+        Ok(Err(e)) => Err(BotError::Transport(format!("{}: {}", STREAM_INTERRUPTED, e))),
+        Err(_) => Err(BotError::Transport(format!("{}: nothing received for {:?}", STREAM_INTERRUPTED, policy.stream_idle_timeout()))),
+    }
+}
+
+// An interrupted stream is requested again from the start, like any other failed attempt
+pub fn is_stream_interruption(e: &BotError) -> bool {
+    matches!(e, BotError::Transport(msg) if msg.starts_with(STREAM_INTERRUPTED))
+}
+
+#[cfg(test)]
+pub mod stub_server {
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+    };
+
+    // Never answers, to exercise timeouts
+    pub const HANG: &str = "HANG";
+
+    pub fn http_response(status: &str, headers: &[(&str, &str)], body: &str) -> String {
+        let mut res = format!("HTTP/1.1 {}\r\nContent-Length: {}\r\nConnection: close\r\n", status, body.len());
+        for (name, value) in headers {
+            res.push_str(&format!("{}: {}\r\n", name, value));
+        }
+        res.push_str("\r\n");
+        res.push_str(body);
+        res
+    }
+
+    // Serves the given raw responses in order, one per connection, repeating the last one.
+    // Returns the base url and a counter of requests received.
+    pub async fn start(responses: Vec<String>) -> (String, Arc<AtomicUsize>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base_url = format!("http://{}", listener.local_addr().unwrap());
+        let hits = Arc::new(AtomicUsize::new(0));
+        let counter = hits.clone();
+        tokio::spawn(async move {
+            loop {
+                let (mut socket, _) = match listener.accept().await {
+                    Ok(conn) => conn,
+                    Err(_) => return,
+                };
+                let n = counter.fetch_add(1, Ordering::SeqCst);
+                let response = responses[n.min(responses.len() - 1)].clone();
+                tokio::spawn(async move {
+                    let mut buf = vec![0u8; 64 * 1024];
+                    let _ = socket.read(&mut buf).await;
+                    if response == HANG {
+                        tokio::time::sleep(std::time::Duration::from_secs(30)).await;
+                        return;
+                    }
+                    let _ = socket.write_all(response.as_bytes()).await;
+                    let _ = socket.shutdown().await;
+                });
+            }
+        });
+        (base_url, hits)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{stub_server::*, *};
+    use reqwest::Client;
+
+    fn fast_policy(max_attempts: u32) -> RetryPolicy {
+        RetryPolicy { max_attempts, initial_backoff_ms: 10, max_backoff_ms: 50, request_timeout_ms: 300, ..Default::default() }
+    }
+
+    #[tokio::test]
+    async fn test_retries_429_honoring_retry_after() {
+        let (url, hits) = start(vec![
+            http_response("429 Too Many Requests", &[("Retry-After", "1")], "slow down"),
+            http_response("200 OK", &[], "ok"),
+        ])
+        .await;
+        let client = Client::new();
+        let started = Instant::now();
+        let res = send_with_retry(|| client.get(&url), &RetryPolicy { max_backoff_ms: 2000, ..fast_policy(3) }, None).await.unwrap();
+        assert_eq!(res.text().await.unwrap(), "ok");
+        assert_eq!(hits.load(Ordering::SeqCst), 2);
+        assert!(started.elapsed() >= Duration::from_secs(1));
+    }
+
+    #[tokio::test]
+    async fn test_retry_after_is_capped() {
+        let client = Client::new();
+        for value in ["inf", "NaN", "3600", "1e300"] {
+            let (url, hits) = start(vec![
+                http_response("429 Too Many Requests", &[("Retry-After", value)], "slow down"),
+                http_response("200 OK", &[], "ok"),
+            ])
+            .await;
+            let started = Instant::now();
+            assert!(send_with_retry(|| client.get(&url), &fast_policy(3), None).await.is_ok());
+            assert_eq!(hits.load(Ordering::SeqCst), 2);
+            assert!(started.elapsed() < Duration::from_secs(1), "Retry-After: {} was not capped", value);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_retries_5xx_but_not_4xx() {
+        let (url, hits) = start(vec![
+            http_response("503 Service Unavailable", &[], ""),
+            http_response("500 Internal Server Error", &[], ""),
+            http_response("200 OK", &[], "ok"),
+        ])
+        .await;
+        let client = Client::new();
+        assert!(send_with_retry(|| client.get(&url), &fast_policy(3), None).await.is_ok());
+        assert_eq!(hits.load(Ordering::SeqCst), 3);
+
+        let (url, hits) = start(vec![http_response("400 Bad Request", &[], "bad model")]).await;
+        let err = send_with_retry(|| client.get(&url), &fast_policy(3), None).await.unwrap_err();
+        assert!(err.to_string().contains("bad model"));
+        assert_eq!(hits.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_hung_requests_time_out() {
+        let (url, hits) = start(vec![HANG.to_string()]).await;
+        let client = Client::new();
+        let started = Instant::now();
+        assert!(send_with_retry(|| client.get(&url), &fast_policy(2), None).await.is_err());
+        assert_eq!(hits.load(Ordering::SeqCst), 2);
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+
+    #[tokio::test]
+    async fn test_rate_limiter_spaces_requests() {
+        // burst of 2, then one every 100ms
+        let limiter = RateLimiter::new(600, 2);
+        let started = Instant::now();
+        for _ in 0..3 {
+            limiter.acquire().await;
+        }
+        assert!(started.elapsed() >= Duration::from_millis(90));
+    }
+
+    #[test]
+    fn test_backoff_grows_and_is_capped() {
+        let policy = RetryPolicy { initial_backoff_ms: 100, max_backoff_ms: 1000, ..Default::default() };
+        let first = policy.backoff(1);
+        assert!(first >= Duration::from_millis(50) && first <= Duration::from_millis(100));
+        let third = policy.backoff(3);
+        assert!(third >= Duration::from_millis(200) && third <= Duration::from_millis(400));
+        assert!(policy.backoff(30) <= Duration::from_millis(1000));
+    }
+}
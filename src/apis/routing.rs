@@ -0,0 +1,180 @@
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use super::{
+    client::HttpTransport,
+    llm_provider::{provider_from_config, LlmProvider, ProviderConfig, ProviderKind, TokenSink},
+};
+use crate::{
+    errors::{BotError, BotResult},
+    models::general::{
+        llm::{APIResponse, Message},
+        schema::OutputSchema,
+    },
+};
+
+// Model and sampling for one agent or AI function. Unset fields fall through to the agent's
+// settings and then to the LLM_* environment.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(default)]
+pub struct ModelSettings {
+    pub provider: Option<String>,
+    pub model: Option<String>,
+    pub temperature: Option<f32>,
+    pub max_tokens: Option<u32>,
+    pub top_p: Option<f32>,
+    pub stop: Option<Vec<String>>,
+    // most tokens of earlier conversation sent along with a prompt, see memory::DEFAULT_HISTORY_TOKENS
+    pub history_tokens: Option<usize>,
+}
+
+impl ModelSettings {
+    // Fields set here win over `fallback`
+    pub fn or(self, fallback: &ModelSettings) -> ModelSettings {
+        let fallback = fallback.clone();
+        ModelSettings {
+            provider: self.provider.or(fallback.provider),
+            model: self.model.or(fallback.model),
+            temperature: self.temperature.or(fallback.temperature),
+            max_tokens: self.max_tokens.or(fallback.max_tokens),
+            top_p: self.top_p.or(fallback.top_p),
+            stop: self.stop.or(fallback.stop),
+            history_tokens: self.history_tokens.or(fallback.history_tokens),
+        }
+    }
+}
+
+// The "models" section of the config file, keyed by agent position ("Backend Developer") and by
+// AI function name ("convert_user_input_to_goal")
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(default)]
+pub struct ModelsConfig {
+    pub agents: HashMap<String, ModelSettings>,
+    pub functions: HashMap<String, ModelSettings>,
+}
+
+impl ModelsConfig {
+    pub fn settings_for(&self, position: &str, ai_function: &str) -> ModelSettings {
+        let agent = self.agents.get(position).cloned().unwrap_or_default();
+        match self.functions.get(ai_function) {
+            Some(function) => function.clone().or(&agent),
+            None => agent,
+        }
+    }
+    // Unknown provider names are reported at startup rather than at the first call that uses them
+    pub fn validate(&self) -> BotResult<()> {
+        let all = self.agents.iter().chain(self.functions.iter());
+        for (name, settings) in all {
+            if let Some(provider) = &settings.provider {
+                if ProviderKind::from_name(provider).is_none() {
+                    return Err(BotError::Config(format!("models.{}: unknown provider {}", name, provider)));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+// Sends each call to the provider its settings ask for. The provider from the environment is
+// built up front; others on first use, so their API keys are only needed when configured.
+pub struct RoutingProvider {
+    default_config: ProviderConfig,
+    transport: HttpTransport,
+    providers: Mutex<HashMap<ProviderKind, Arc<dyn LlmProvider>>>,
+}
+
+// The model a call with these settings ends up on: its own, else the default of the provider it
+// is routed to. Needs no provider, so a replay resolves models without any API keys.
+pub fn routed_model(default_config: &ProviderConfig, settings: &ModelSettings) -> String {
+    if let Some(model) = &settings.model {
+        return model.clone();
+    }
+    match settings.provider.as_deref().and_then(ProviderKind::from_name) {
+        Some(kind) if kind != default_config.kind => ProviderConfig::for_kind(kind, default_config).model,
+        _ => default_config.model.clone(),
+    }
+}
+
+impl RoutingProvider {
+    pub fn new(default_config: ProviderConfig, transport: &HttpTransport) -> BotResult<Self> {
+        let default_provider: Arc<dyn LlmProvider> = provider_from_config(default_config.clone(), transport)?.into();
+        let providers = HashMap::from([(default_config.kind, default_provider)]);
+        Ok(Self { default_config, transport: transport.clone(), providers: Mutex::new(providers) })
+    }
+    fn provider_for(&self, settings: &ModelSettings) -> BotResult<Arc<dyn LlmProvider>> {
+        let kind = match &settings.provider {
+            Some(name) => ProviderKind::from_name(name)
+                .ok_or(BotError::Config(format!("unknown provider {}", name)))?,
+            None => self.default_config.kind,
+        };
+        let mut providers = self.providers.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(provider) = providers.get(&kind) {
+            return Ok(provider.clone());
+        }
+        let config = ProviderConfig::for_kind(kind, &self.default_config);
+        let provider: Arc<dyn LlmProvider> = provider_from_config(config, &self.transport)?.into();
+        providers.insert(kind, provider.clone());
+        Ok(provider)
+    }
+}
+
+#[async_trait]
+impl LlmProvider for RoutingProvider {
+    fn model(&self) -> &str {
+        &self.default_config.model
+    }
+    fn model_for(&self, settings: &ModelSettings) -> String {
+        routed_model(&self.default_config, settings)
+    }
+    async fn complete(&self, messages: Vec<Message>, settings: &ModelSettings) -> BotResult<APIResponse> {
+        self.provider_for(settings)?.complete(messages, settings).await
+    }
+    async fn complete_streaming(&self, messages: Vec<Message>, settings: &ModelSettings, on_token: &TokenSink<'_>) -> BotResult<APIResponse> {
+        self.provider_for(settings)?.complete_streaming(messages, settings, on_token).await
+    }
+    async fn complete_structured(
+        &self,
+        messages: Vec<Message>,
+        schema: &OutputSchema,
+        settings: &ModelSettings,
+        on_token: &TokenSink<'_>,
+    ) -> BotResult<APIResponse> {
+        self.provider_for(settings)?.complete_structured(messages, schema, settings, on_token).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_function_settings_override_agent_settings() {
+        let config: ModelsConfig = serde_json::from_str(
+            r#"{
+                "agents": { "Backend Developer": { "model": "gpt-4o", "temperature": 0.2, "stop": ["```"] } },
+                "functions": {
+                    "convert_user_input_to_goal": { "model": "gpt-4o-mini" },
+                    "print_fixed_code": { "temperature": 0.0, "max_tokens": 8000 }
+                }
+            }"#,
+        )
+        .unwrap();
+        let fix = config.settings_for("Backend Developer", "print_fixed_code");
+        assert_eq!(fix.model.as_deref(), Some("gpt-4o"));
+        assert_eq!(fix.temperature, Some(0.0));
+        assert_eq!(fix.max_tokens, Some(8000));
+        assert_eq!(fix.stop, Some(vec!["```".to_string()]));
+
+        let goal = config.settings_for("Project Manager", "convert_user_input_to_goal");
+        assert_eq!(goal.model.as_deref(), Some("gpt-4o-mini"));
+        assert_eq!(goal.temperature, None);
+        assert!(config.validate().is_ok());
+
+        let bad: ModelsConfig = serde_json::from_str(r#"{ "agents": { "Backend Developer": { "provider": "gemini" } } }"#).unwrap();
+        assert!(matches!(bad.validate(), Err(BotError::Config(_))));
+    }
+}
@@ -0,0 +1,89 @@
+// Server-sent events as used by the OpenAI and Anthropic streaming APIs
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SseEvent {
+    pub event: Option<String>,
+    pub data: String,
+}
+
+// Incremental parser. Network chunks can end anywhere, even inside a UTF-8 character, so bytes
+// are buffered until a blank line completes an event.
+#[derive(Debug, Default)]
+pub struct SseParser {
+    buffer: Vec<u8>,
+}
+
+fn find_event_end(buffer: &[u8]) -> Option<(usize, usize)> {
+    let lf = buffer.windows(2).position(|w| w == b"\n\n").map(|i| (i, 2));
+    let crlf = buffer.windows(4).position(|w| w == b"\r\n\r\n").map(|i| (i, 4));
+    match (lf, crlf) {
+        (Some(a), Some(b)) => Some(if a.0 < b.0 { a } else { b }),
+        (a, b) => a.or(b),
+    }
+}
+
+fn parse_event(block: &str) -> Option<SseEvent> {
+    let mut event = SseEvent::default();
+    let mut data_lines: Vec<&str> = vec![];
+    for line in block.lines() {
+        // lines starting with ':' are comments, used as keep-alives
+        if line.is_empty() || line.starts_with(':') {
+            continue;
+        }
+        let (field, value) = match line.split_once(':') {
+            Some((field, value)) => (field, value.strip_prefix(' ').unwrap_or(value)),
+            None => (line, ""),
+        };
+        match field {
+            "event" => event.event = Some(value.to_string()),
+            "data" => data_lines.push(value),
+            _ => {}
+        }
+    }
+    if data_lines.is_empty() {
+        return None;
+    }
+    event.data = data_lines.join("\n");
+    Some(event)
+}
+
+impl SseParser {
+    pub fn push(&mut self, chunk: &[u8]) -> Vec<SseEvent> {
+        self.buffer.extend_from_slice(chunk);
+        let mut events = vec![];
+        while let Some((end, sep_len)) = find_event_end(&self.buffer) {
+            let block: Vec<u8> = self.buffer.drain(..end + sep_len).collect();
+            if let Some(event) = parse_event(&String::from_utf8_lossy(&block[..end])) {
+                events.push(event);
+            }
+        }
+        events
+    }
+    // Whatever is left when the connection closes without a final blank line
+    pub fn finish(&mut self) -> Option<SseEvent> {
+        let block: Vec<u8> = self.buffer.drain(..).collect();
+        parse_event(&String::from_utf8_lossy(&block))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_events_split_across_chunks() {
+        let stream = "event: content_block_delta\ndata: {\"text\":\"h\u{e9}\"}\n\n: ping\n\ndata: [DONE]\r\n\r\ndata: tail";
+        let bytes = stream.as_bytes();
+        let mut parser = SseParser::default();
+        let mut events = vec![];
+        // one byte at a time, splitting the two byte 'é' too
+        for b in bytes {
+            events.extend(parser.push(std::slice::from_ref(b)));
+        }
+        events.extend(parser.finish());
+        assert_eq!(events.len(), 3);
+        assert_eq!(events[0].event.as_deref(), Some("content_block_delta"));
+        assert_eq!(events[0].data, "{\"text\":\"h\u{e9}\"}");
+        assert_eq!(events[1].data, "[DONE]");
+        assert_eq!(events[2].data, "tail");
+    }
+}
@@ -0,0 +1,108 @@
+use std::fmt;
+
+// Every way a run can fail. Agents return these instead of panicking so the
+// manager can stop cleanly and report what went wrong.
+#[derive(Debug)]
+pub enum BotError {
+    // missing env vars, bad config values
+    Config(String),
+    // network / HTTP failures talking to the LLM or other services
+    Transport(String),
+    // the LLM answered but we could not make sense of the answer
+    LlmDecode { ai_function: String, message: String, raw: String },
+    // generated code still does not compile after the allowed fix attempts
+    BuildFailure { attempts: u8, errors: String },
+    // generated code builds but still breaks denied lints after the allowed fix attempts
+    LintFailure { attempts: u8, lints: String },
+    // the generated server ran but an endpoint did not behave
+    EndpointTest { route: String, message: String },
+    // the user declined to continue at a confirmation prompt
+    UserAbort(String),
+    // the run spent more on LLM calls than the configured budget
+    BudgetExceeded { spent_usd: f64, budget_usd: f64 },
+    // generated code asked to write a file outside the part of the workspace it may change
+    UnsafePath { path: String, reason: String },
+    // generated edits did not match the files they were meant to change
+    PatchConflict { conflicts: Vec<String> },
+    // generated code asked for a crate the dependency allowlist does not permit
+    DependencyRejected { crate_name: String, reason: String },
+    Io(std::io::Error),
+}
+
+impl BotError {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::Config(_) => "config",
+            Self::Transport(_) => "transport",
+            Self::LlmDecode { .. } => "llm_decode",
+            Self::BuildFailure { .. } => "build_failure",
+            Self::LintFailure { .. } => "lint_failure",
+            Self::EndpointTest { .. } => "endpoint_test",
+            Self::UserAbort(_) => "user_abort",
+            Self::BudgetExceeded { .. } => "budget_exceeded",
+            Self::UnsafePath { .. } => "unsafe_path",
+            Self::PatchConflict { .. } => "patch_conflict",
+            Self::DependencyRejected { .. } => "dependency_rejected",
+            Self::Io(_) => "io",
+        }
+    }
+}
+
+impl fmt::Display for BotError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Config(msg) => write!(f, "configuration error: {}", msg),
+            Self::Transport(msg) => write!(f, "transport error: {}", msg),
+            Self::LlmDecode { ai_function, message, raw } => {
+                let excerpt: String = raw.chars().take(500).collect();
+                write!(f, "could not decode LLM response for {}: {}\nraw response: {}", ai_function, message, excerpt)
+            }
+            Self::BuildFailure { attempts, errors } => {
+                write!(f, "build still failing after {} attempts:\n{}", attempts, errors)
+            }
+            Self::LintFailure { attempts, lints } => {
+                write!(f, "denied lints still failing after {} attempts:\n{}", attempts, lints)
+            }
+            Self::EndpointTest { route, message } => write!(f, "endpoint {} failed: {}", route, message),
+            Self::UserAbort(msg) => write!(f, "aborted by user: {}", msg),
+            Self::BudgetExceeded { spent_usd, budget_usd } => {
+                write!(f, "LLM budget of ${:.2} exceeded, ${:.4} spent", budget_usd, spent_usd)
+            }
+            Self::UnsafePath { path, reason } => write!(f, "refusing to write generated file {:?}: {}", path, reason),
+            Self::PatchConflict { conflicts } => {
+                write!(f, "{} generated edits could not be applied:\n- {}", conflicts.len(), conflicts.join("\n- "))
+            }
+            Self::DependencyRejected { crate_name, reason } => write!(f, "dependency {} rejected: {}", crate_name, reason),
+            Self::Io(e) => write!(f, "io error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for BotError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for BotError {
+    fn from(e: std::io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
+impl From<reqwest::Error> for BotError {
+    fn from(e: reqwest::Error) -> Self {
+        Self::Transport(e.to_string())
+    }
+}
+
+impl From<reqwest::header::InvalidHeaderValue> for BotError {
+    fn from(e: reqwest::header::InvalidHeaderValue) -> Self {
+        Self::Config(format!("invalid header value: {}", e))
+    }
+}
+
+pub type BotResult<T> = Result<T, BotError>;
@@ -0,0 +1,129 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+// Longest captured output of a failing test that goes into a report
+const MAX_FAILURE_OUTPUT_CHARS: usize = 2_000;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct TestResult {
+    pub name: String,
+    pub passed: bool,
+    pub output: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct TestReport {
+    pub results: Vec<TestResult>,
+}
+
+impl TestReport {
+    pub fn failures(&self) -> Vec<&TestResult> {
+        self.results.iter().filter(|r| !r.passed).collect()
+    }
+    // The failing tests among `names`. libtest qualifies names with their module
+    // (api_tests::post_item_creates_item), a model may answer with or without it.
+    pub fn failures_named(&self, names: &[String]) -> Vec<&TestResult> {
+        let bare = |name: &str| name.rsplit("::").next().unwrap_or_default().trim().to_string();
+        let names: Vec<String> = names.iter().map(|n| bare(n)).collect();
+        self.failures().into_iter().filter(|r| names.contains(&bare(&r.name))).collect()
+    }
+    pub fn all_passed(&self) -> bool {
+        !self.results.is_empty() && self.results.iter().all(|r| r.passed)
+    }
+    // Plain text report, failing tests with what they printed. This goes back to the LLM.
+    pub fn summary(&self) -> String {
+        self.results
+            .iter()
+            .map(|r| match r.passed {
+                true => format!("PASS {}", r.name),
+                false => format!("FAIL {}\n{}", r.name, r.output),
+            })
+            .collect::<Vec<String>>()
+            .join("\n")
+    }
+}
+
+// Per test results from the stdout of `cargo test`. libtest's own report is plain text between
+// cargo's JSON lines: a `test <name> ... ok|FAILED` line per test, then a `---- <name> stdout ----`
+// section with the output of every failing one. Ignored tests are left out.
+pub fn parse_test_results(stdout: &str) -> TestReport {
+    let mut outputs: HashMap<String, Vec<&str>> = HashMap::new();
+    let mut current: Option<String> = None;
+    let mut results = vec![];
+    for line in stdout.lines() {
+        if let Some(name) = line.strip_prefix("---- ").and_then(|l| l.strip_suffix(" stdout ----")) {
+            current = Some(name.to_string());
+            continue;
+        }
+        if line == "failures:" || line.starts_with("test result:") || line.starts_with('{') {
+            current = None;
+        }
+        if let Some(name) = &current {
+            outputs.entry(name.clone()).or_default().push(line);
+            continue;
+        }
+        let Some((name, verdict)) = line.strip_prefix("test ").and_then(|l| l.rsplit_once(" ... ")) else {
+            continue;
+        };
+        match verdict.trim() {
+            "ok" => results.push(TestResult { name: name.to_string(), passed: true, output: String::new() }),
+            "FAILED" => results.push(TestResult { name: name.to_string(), passed: false, output: String::new() }),
+            _ => {}
+        }
+    }
+    for result in results.iter_mut().filter(|r| !r.passed) {
+        let output = outputs.get(&result.name).map(|lines| lines.join("\n")).unwrap_or_default();
+        result.output = output.trim().chars().take(MAX_FAILURE_OUTPUT_CHARS).collect();
+    }
+    TestReport { results }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CARGO_TEST_STDOUT: &str = r#"{"reason":"compiler-artifact","package_id":"app 0.1.0","target":{"name":"app"},"fresh":false}
+{"reason":"build-finished","success":true}
+
+running 3 tests
+test api_tests::list_tasks_returns_ok ... ok
+test api_tests::create_task_returns_created ... FAILED
+test api_tests::slow_test ... ignored
+
+failures:
+
+---- api_tests::create_task_returns_created stdout ----
+thread 'api_tests::create_task_returns_created' panicked at src/api_tests.rs:21:9:
+assertion `left == right` failed
+  left: 200
+ right: 201
+
+failures:
+    api_tests::create_task_returns_created
+
+test result: FAILED. 1 passed; 1 failed; 1 ignored; 0 measured; 0 filtered out; finished in 0.01s
+"#;
+
+    #[test]
+    fn test_parse_test_results() {
+        let report = parse_test_results(CARGO_TEST_STDOUT);
+        assert_eq!(report.results.len(), 2);
+        assert!(!report.all_passed());
+        let failures = report.failures();
+        assert_eq!(failures[0].name, "api_tests::create_task_returns_created");
+        assert!(failures[0].output.starts_with("thread 'api_tests::create_task_returns_created' panicked at src/api_tests.rs:21:9"));
+        assert!(failures[0].output.ends_with("right: 201"));
+        assert!(report.summary().starts_with("PASS api_tests::list_tasks_returns_ok\nFAIL api_tests::create_task_returns_created"));
+        assert!(!parse_test_results("").all_passed());
+    }
+
+    #[test]
+    fn test_failures_named_ignores_the_module_path() {
+        let report = parse_test_results(CARGO_TEST_STDOUT);
+        // a bare name from the model, a qualified one, and a test that passed
+        let wrong = report.failures_named(&["create_task_returns_created".to_string()]);
+        assert_eq!(wrong.iter().map(|r| r.name.as_str()).collect::<Vec<&str>>(), vec!["api_tests::create_task_returns_created"]);
+        assert_eq!(report.failures_named(&["api_tests::create_task_returns_created".to_string()]).len(), 1);
+        assert!(report.failures_named(&["list_tasks_returns_ok".to_string()]).is_empty());
+    }
+}
@@ -0,0 +1,291 @@
+use serde_json::json;
+use std::{io::Read, path::PathBuf};
+
+use crate::{
+    errors::{BotError, BotResult},
+    models::{
+        agent_basic::basic_agent::AgentState,
+        agents::agent_traits::FactSheet,
+        agents_manager::session::{Checkpoint, Session},
+        general::usage::usage_report,
+    },
+};
+
+use super::command_line::ApprovePolicy;
+
+pub const USAGE: &str = "usage: code_bot_hannah [COMMAND] [OPTIONS]
+
+commands:
+  new [REQUEST...]       start a run (default); REQUEST is asked for on stdin when omitted
+  resume <SESSION>       continue a run from its last checkpoint
+  list                   list saved sessions
+  show <SESSION>         print the checkpoint of a session
+  replay <SESSION>       re-run a session from its recorded LLM responses, without network calls,
+                         into <SESSION DIR>/replay unless --workspace is given
+
+options:
+  --request-file <FILE>  read the request from FILE, `-` for stdin
+  --approve <POLICY>     always, never or ask (default) before running generated code; ask
+                         declines when stdin is not a terminal
+  --provider <NAME>      LLM provider, same values as LLM_PROVIDER
+  --model <NAME>         LLM model, same as LLM_MODEL
+  --no-stream            wait for whole answers instead of printing them as they arrive
+  --budget <USD>         stop the run once LLM calls have cost this much
+  --workspace <DIR>      directory of the generated project. An existing git repository
+                         there must not have uncommitted changes
+  --config <FILE>        config file (default hannah.json)
+  --json                 machine readable output on stdout, progress goes to stderr
+  -h, --help             print this message";
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum RequestSource {
+    Inline(String),
+    File(PathBuf),
+    Prompt,
+}
+
+impl RequestSource {
+    pub fn read(&self) -> BotResult<Option<String>> {
+        match self {
+            Self::Inline(request) => Ok(Some(request.clone())),
+            Self::File(path) if path.as_os_str() == "-" => {
+                let mut request = String::new();
+                std::io::stdin().read_to_string(&mut request)?;
+                Ok(Some(request.trim().to_string()))
+            }
+            Self::File(path) => Ok(Some(std::fs::read_to_string(path)?.trim().to_string())),
+            Self::Prompt => Ok(None),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum CliCommand {
+    New(RequestSource),
+    Resume(String),
+    List,
+    Show(String),
+    Replay(String),
+    Help,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CliArgs {
+    pub command: CliCommand,
+    pub config: Option<PathBuf>,
+    pub workspace: Option<PathBuf>,
+    pub provider: Option<String>,
+    pub model: Option<String>,
+    pub approve: ApprovePolicy,
+    pub budget_usd: Option<f64>,
+    pub stream: bool,
+    pub json: bool,
+}
+
+fn usage_error(message: String) -> BotError {
+    BotError::Config(format!("{}\n\n{}", message, USAGE))
+}
+
+// Parses everything after the program name. Options may appear before or after the command,
+// as `--flag value` or `--flag=value`.
+pub fn parse_args(args: &[String]) -> BotResult<CliArgs> {
+    let mut positional: Vec<String> = vec![];
+    let mut request_file: Option<PathBuf> = None;
+    let mut cli = CliArgs {
+        command: CliCommand::Help,
+        config: None,
+        workspace: None,
+        provider: None,
+        model: None,
+        approve: ApprovePolicy::Ask,
+        budget_usd: None,
+        stream: true,
+        json: false,
+    };
+    let mut i = 0;
+    while i < args.len() {
+        let arg = &args[i];
+        i += 1;
+        if !arg.starts_with('-') || arg == "-" {
+            positional.push(arg.clone());
+            continue;
+        }
+        let (flag, inline_value) = match arg.split_once('=') {
+            Some((flag, value)) => (flag, Some(value.to_string())),
+            None => (arg.as_str(), None),
+        };
+        match flag {
+            "-h" | "--help" => return Ok(CliArgs { command: CliCommand::Help, ..cli }),
+            "--json" => {
+                cli.json = true;
+                continue;
+            }
+            "--no-stream" => {
+                cli.stream = false;
+                continue;
+            }
+            _ => {}
+        }
+        let value = match inline_value {
+            Some(value) => value,
+            None => {
+                i += 1;
+                args.get(i - 1).cloned().ok_or_else(|| usage_error(format!("{} needs a value", flag)))?
+            }
+        };
+        match flag {
+            "--request-file" => request_file = Some(PathBuf::from(value)),
+            "--approve" => {
+                cli.approve = ApprovePolicy::from_name(&value)
+                    .ok_or_else(|| usage_error(format!("--approve must be always, never or ask, got {}", value)))?
+            }
+            "--budget" => {
+                cli.budget_usd = Some(value.parse().map_err(|_| usage_error(format!("--budget must be a number of dollars, got {}", value)))?)
+            }
+            "--provider" => cli.provider = Some(value),
+            "--model" => cli.model = Some(value),
+            "--workspace" => cli.workspace = Some(PathBuf::from(value)),
+            "--config" => cli.config = Some(PathBuf::from(value)),
+            _ => return Err(usage_error(format!("unknown option {}", flag))),
+        }
+    }
+
+    let command = positional.first().cloned();
+    let rest: Vec<String> = positional.into_iter().skip(1).collect();
+    let session_arg = |name: &str| -> BotResult<String> {
+        match rest.as_slice() {
+            [id] => Ok(id.clone()),
+            [] => Err(usage_error(format!("{} needs a session id", name))),
+            [_, extra, ..] => Err(usage_error(format!("unexpected argument {}", extra))),
+        }
+    };
+    cli.command = match command.as_deref() {
+        None | Some("new") => {
+            // an unquoted request arrives as several arguments
+            let request = if rest.is_empty() { None } else { Some(rest.join(" ")) };
+            match (request, request_file) {
+                (Some(_), Some(_)) => return Err(usage_error("give the request either inline or with --request-file".to_string())),
+                (Some(request), None) => CliCommand::New(RequestSource::Inline(request)),
+                (None, Some(path)) => CliCommand::New(RequestSource::File(path)),
+                (None, None) => CliCommand::New(RequestSource::Prompt),
+            }
+        }
+        Some("resume") => CliCommand::Resume(session_arg("resume")?),
+        Some("show") => CliCommand::Show(session_arg("show")?),
+        Some("replay") => CliCommand::Replay(session_arg("replay")?),
+        Some("list") | Some("help") if !rest.is_empty() => return Err(usage_error(format!("unexpected argument {}", rest[0]))),
+        Some("list") => CliCommand::List,
+        Some("help") => CliCommand::Help,
+        Some(other) => return Err(usage_error(format!("unknown command {}", other))),
+    };
+    Ok(cli)
+}
+
+fn checkpoint_status(checkpoint: &Checkpoint) -> &'static str {
+    if !checkpoint.agents.is_empty() && checkpoint.agents.iter().all(|a| a.state == AgentState::Finished) {
+        "finished"
+    } else {
+        "in progress"
+    }
+}
+
+pub fn print_session_list(sessions: &[Session], json_output: bool) -> BotResult<()> {
+    let mut rows = vec![];
+    for session in sessions {
+        let checkpoint = session.load()?;
+        rows.push(json!({
+            "session_id": session.id,
+            "status": checkpoint_status(&checkpoint),
+            "user_request": checkpoint.user_request,
+            "workspace": checkpoint.workspace.root,
+        }));
+    }
+    if json_output {
+        println!("{}", serde_json::Value::Array(rows));
+        return Ok(());
+    }
+    for row in rows {
+        println!("{}  {:<12}  {}", row["session_id"].as_str().unwrap_or_default(), row["status"].as_str().unwrap_or_default(), row["user_request"].as_str().unwrap_or_default());
+    }
+    Ok(())
+}
+
+pub fn print_checkpoint(checkpoint: &Checkpoint, json_output: bool) {
+    if json_output {
+        println!("{}", serde_json::to_string_pretty(checkpoint).unwrap_or_default());
+        return;
+    }
+    println!("Session:     {} ({})", checkpoint.session_id, checkpoint_status(checkpoint));
+    println!("Request:     {}", checkpoint.user_request);
+    println!("Workspace:   {}", checkpoint.workspace.root.display());
+    println!("Description: {}", checkpoint.factsheet.project_description);
+    println!("Cost:        ${:.4} ({} tokens)", checkpoint.usage.total_cost(), checkpoint.usage.total_tokens());
+    for agent in &checkpoint.agents {
+        println!("  {:<28} {:?} (bugs: {})", agent.position, agent.state, agent.bug_count);
+    }
+}
+
+// The document printed on stdout at the end of a run in --json mode
+pub fn print_run_result(session_id: &str, result: &BotResult<()>, factsheet: &FactSheet) {
+    let error = match result {
+        Ok(()) => serde_json::Value::Null,
+        Err(e) => json!({ "kind": e.kind(), "message": e.to_string() }),
+    };
+    let summary = json!({
+        "session_id": session_id,
+        "status": if result.is_ok() { "finished" } else { "failed" },
+        "error": error,
+        "usage": usage_report(),
+        "factsheet": factsheet,
+    });
+    println!("{}", serde_json::to_string_pretty(&summary).unwrap_or_default());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(line: &str) -> BotResult<CliArgs> {
+        let args: Vec<String> = line.split_whitespace().map(|a| a.to_string()).collect();
+        parse_args(&args)
+    }
+
+    #[test]
+    fn test_parse_commands_and_flags() {
+        let cli = parse("").unwrap();
+        assert_eq!(cli.command, CliCommand::New(RequestSource::Prompt));
+        assert_eq!(cli.approve, ApprovePolicy::Ask);
+
+        let cli = parse("new todo_app --approve=always --model gpt-4o --json --workspace /tmp/ws --budget 2.5").unwrap();
+        assert_eq!(cli.command, CliCommand::New(RequestSource::Inline("todo_app".to_string())));
+        assert_eq!(cli.approve, ApprovePolicy::Always);
+        assert_eq!(cli.model.as_deref(), Some("gpt-4o"));
+        assert_eq!(cli.workspace, Some(PathBuf::from("/tmp/ws")));
+        assert_eq!(cli.budget_usd, Some(2.5));
+        assert!(cli.json);
+
+        let cli = parse("--config ci.json new --request-file -").unwrap();
+        assert_eq!(cli.command, CliCommand::New(RequestSource::File(PathBuf::from("-"))));
+        assert_eq!(cli.config, Some(PathBuf::from("ci.json")));
+
+        assert_eq!(parse("resume 1700000000-123").unwrap().command, CliCommand::Resume("1700000000-123".to_string()));
+        assert_eq!(parse("list --json").unwrap().command, CliCommand::List);
+        assert!(!parse("resume 1 --no-stream").unwrap().stream);
+        assert_eq!(parse("show --help").unwrap().command, CliCommand::Help);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(parse("resume").is_err());
+        assert!(parse("new --approve sometimes").is_err());
+        assert!(parse("new todo --request-file req.txt").is_err());
+        assert!(parse("deploy").is_err());
+        assert!(parse("--model").is_err());
+        assert!(parse("new --budget lots").is_err());
+        assert!(parse("list extra").is_err());
+        assert_eq!(
+            parse("new a todo app").unwrap().command,
+            CliCommand::New(RequestSource::Inline("a todo app".to_string()))
+        );
+    }
+}
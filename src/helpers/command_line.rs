@@ -2,6 +2,26 @@ use crossterm::{
     style::{Color,ResetColor, SetForegroundColor},
     ExecutableCommand,
 };
+use std::{io::{BufRead, IsTerminal, Write}, sync::atomic::{AtomicBool, Ordering}};
+
+use crate::errors::{BotError, BotResult};
+
+// In --json mode stdout carries only the final JSON document, so progress goes to stderr
+static PROGRESS_TO_STDERR: AtomicBool = AtomicBool::new(false);
+// Set while streamed tokens are being printed, so the line can be closed afterwards
+static STREAM_OPEN: AtomicBool = AtomicBool::new(false);
+
+fn progress_out() -> Box<dyn Write> {
+    if PROGRESS_TO_STDERR.load(Ordering::Relaxed) {
+        Box::new(std::io::stderr())
+    } else {
+        Box::new(std::io::stdout())
+    }
+}
+
+pub fn set_progress_to_stderr(enabled: bool) {
+    PROGRESS_TO_STDERR.store(enabled, Ordering::Relaxed);
+}
 
 #[derive(Debug,PartialEq)]
 pub enum PrintCommand {
@@ -11,44 +31,106 @@ pub enum PrintCommand {
 }
 
 impl PrintCommand {
-    pub fn print_agent_message(&self, agent_pos: &str, agent_statement: &str) {
-        let mut stdout: std::io::Stdout = std::io::stdout();
-        let statement_color = match self {
+    fn color(&self) -> Color {
+        match self {
             Self::AICall => Color::Cyan,
             Self::UnitTest => Color::Magenta,
             Self::Issue => Color::Red,
-        };
-        stdout.execute(SetForegroundColor(Color::Green)).unwrap();
-        println!("Agent {}: ", agent_pos);
-        stdout.execute(SetForegroundColor(statement_color)).unwrap();
-        println!("{}", agent_statement);
-        stdout.execute(ResetColor).unwrap();
+        }
+    }
+    pub fn print_agent_message(&self, agent_pos: &str, agent_statement: &str) {
+        let mut out = progress_out();
+        let statement_color = self.color();
+        out.execute(SetForegroundColor(Color::Green)).unwrap();
+        writeln!(out, "Agent {}: ", agent_pos).unwrap();
+        out.execute(SetForegroundColor(statement_color)).unwrap();
+        writeln!(out, "{}", agent_statement).unwrap();
+        out.execute(ResetColor).unwrap();
     } 
+    // Prints part of a streamed answer under the last agent banner, without a newline. The
+    // preview is only for watching, so a closed pipe does not stop the call.
+    pub fn print_stream_token(&self, token: &str) {
+        let mut out = progress_out();
+        let _ = out.execute(SetForegroundColor(Color::DarkGrey));
+        let _ = write!(out, "{}", token);
+        let _ = out.execute(ResetColor);
+        let _ = out.flush();
+        STREAM_OPEN.store(true, Ordering::Relaxed);
+    }
+    pub fn end_stream(&self) {
+        if STREAM_OPEN.swap(false, Ordering::Relaxed) {
+            let _ = writeln!(progress_out());
+        }
+    }
 }
 
 
 
-pub fn get_user_response(question: &str) -> String {
-    let mut stdout: std::io::Stdout = std::io::stdout();
-    stdout.execute(SetForegroundColor(Color::Blue)).unwrap();
-    println!("");
-    println!("{}", question);
-    stdout.execute(ResetColor).unwrap();
+// Asks on the progress stream, so --json output stays clean
+pub fn get_user_response(question: &str) -> BotResult<String> {
+    let mut out = progress_out();
+    out.execute(SetForegroundColor(Color::Blue))?;
+    writeln!(out)?;
+    writeln!(out, "{}", question)?;
+    out.execute(ResetColor)?;
+    read_answer(&mut std::io::stdin().lock())
+}
+
+fn read_answer(input: &mut impl BufRead) -> BotResult<String> {
     let mut user_response = String::new();
-    std::io::stdin().read_line(&mut user_response).expect("Failed to read line");
-    return user_response.trim().to_string();
-} 
+    if input.read_line(&mut user_response)? == 0 {
+        return Err(BotError::UserAbort("stdin closed before an answer was given".to_string()));
+    }
+    Ok(user_response.trim().to_string())
+}
 
 
-pub fn confirm_safe_to_proceed() -> bool {
-    let mut stdout: std::io::Stdout = std::io::stdout();
+// What to do when generated code is about to be compiled and run
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ApprovePolicy {
+    Always,
+    Never,
+    Ask,
+}
+
+impl ApprovePolicy {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "always" | "yes" => Some(Self::Always),
+            "never" | "no" => Some(Self::Never),
+            "ask" => Some(Self::Ask),
+            _ => None,
+        }
+    }
+}
+
+pub fn confirm_safe_to_proceed(policy: ApprovePolicy) -> bool {
+    match policy {
+        ApprovePolicy::Always => return true,
+        ApprovePolicy::Never => return false,
+        ApprovePolicy::Ask => {}
+    }
+    let mut out = progress_out();
+    // nobody there to answer, e.g. in CI
+    if !std::io::stdin().is_terminal() {
+        writeln!(out, "stdin is not a terminal, declining. Pass --approve always or --approve never to run unattended.").unwrap();
+        return false;
+    }
+    ask_to_proceed(&mut std::io::stdin().lock(), &mut out)
+}
+
+// Asks until the answer is y or n. The end of the input counts as n.
+fn ask_to_proceed(input: &mut impl BufRead, out: &mut dyn Write) -> bool {
     loop {
-        stdout.execute(SetForegroundColor(Color::Blue)).unwrap();
-        println!("");
-        println!("Are you sure you want to proceed? (y/n)");
-        stdout.execute(ResetColor).unwrap();
+        out.execute(SetForegroundColor(Color::Blue)).unwrap();
+        writeln!(out).unwrap();
+        writeln!(out, "Are you sure you want to proceed? (y/n)").unwrap();
+        out.execute(ResetColor).unwrap();
         let mut user_response = String::new();
-        std::io::stdin().read_line(&mut user_response).expect("Failed to read line");
+        match input.read_line(&mut user_response) {
+            Ok(0) | Err(_) => return false,
+            Ok(_) => {}
+        }
         let user_response = user_response.trim().to_string();
         if user_response == "y" {
             return true;
@@ -56,4 +138,24 @@ pub fn confirm_safe_to_proceed() -> bool {
             return false;
         }
     }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ask_to_proceed_treats_end_of_input_as_no() {
+        let mut out: Vec<u8> = vec![];
+        assert!(ask_to_proceed(&mut "maybe\ny\n".as_bytes(), &mut out));
+        assert_eq!(String::from_utf8_lossy(&out).matches("Are you sure").count(), 2);
+        assert!(!ask_to_proceed(&mut "maybe\n".as_bytes(), &mut vec![]));
+        assert!(!ask_to_proceed(&mut "".as_bytes(), &mut vec![]));
+    }
+
+    #[test]
+    fn test_read_answer_reports_closed_stdin() {
+        assert_eq!(read_answer(&mut " a todo app \n".as_bytes()).unwrap(), "a todo app");
+        assert!(matches!(read_answer(&mut "".as_bytes()), Err(BotError::UserAbort(_))));
+    }
 }
\ No newline at end of file
@@ -0,0 +1,294 @@
+use reqwest::{Client, Method};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+
+use crate::models::agents::agent_traits::RouteObject;
+
+const REQUEST_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RouteResult {
+    pub method: String,
+    pub route: String,
+    pub url: String,
+    pub status: Option<u16>,
+    pub passed: bool,
+    pub message: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct ContractReport {
+    pub results: Vec<RouteResult>,
+}
+
+impl ContractReport {
+    pub fn failures(&self) -> Vec<&RouteResult> {
+        self.results.iter().filter(|r| !r.passed).collect()
+    }
+    pub fn all_passed(&self) -> bool {
+        self.results.iter().all(|r| r.passed)
+    }
+    // Plain text report, this is what goes back to the LLM as ERROR_BUGS
+    pub fn summary(&self) -> String {
+        self.results
+            .iter()
+            .map(|r| {
+                let status = r.status.map(|s| s.to_string()).unwrap_or("no response".to_string());
+                let verdict = if r.passed { "PASS" } else { "FAIL" };
+                format!("{} {} {} ({}): {}", verdict, r.method.to_uppercase(), r.url, status, r.message)
+            })
+            .collect::<Vec<String>>()
+            .join("\n")
+    }
+}
+
+// RouteObject uses strings such as "None" or "not_provided" when there is no schema
+fn is_empty_schema(schema: &Value) -> bool {
+    match schema {
+        Value::Null => true,
+        Value::String(s) => matches!(s.to_lowercase().as_str(), "none" | "null" | "not_provided" | ""),
+        _ => false,
+    }
+}
+
+fn type_name(schema: &str) -> String {
+    schema.trim().to_lowercase()
+}
+
+fn is_number_type(name: &str) -> bool {
+    matches!(
+        name,
+        "number" | "integer" | "int" | "float" | "u8" | "u16" | "u32" | "u64" | "usize" | "i8" | "i16" | "i32" | "i64" | "isize" | "f32" | "f64"
+    )
+}
+
+// Builds an example value matching a RouteObject schema ("number", "string", "bool", objects, arrays)
+pub fn synthesize_value(schema: &Value) -> Value {
+    match schema {
+        Value::Object(fields) => {
+            let mut obj = Map::new();
+            for (key, field_schema) in fields {
+                obj.insert(key.clone(), synthesize_value(field_schema));
+            }
+            Value::Object(obj)
+        }
+        Value::Array(items) => match items.first() {
+            Some(item) => Value::Array(vec![synthesize_value(item)]),
+            None => Value::Array(vec![]),
+        },
+        Value::String(s) => {
+            let name = type_name(s);
+            if is_number_type(&name) {
+                Value::from(1)
+            } else if name == "bool" || name == "boolean" {
+                Value::Bool(true)
+            } else {
+                Value::String("test".to_string())
+            }
+        }
+        other => other.clone(),
+    }
+}
+
+// Checks a response body has the shape the schema promises. Nulls are accepted for any
+// field since generated structs often use Option.
+pub fn validate_value(schema: &Value, body: &Value, path: &str) -> Result<(), String> {
+    if is_empty_schema(schema) || body.is_null() {
+        return Ok(());
+    }
+    match schema {
+        Value::Object(fields) => match body {
+            Value::Object(obj) => {
+                for (key, field_schema) in fields {
+                    let field_path = format!("{}.{}", path, key);
+                    match obj.get(key) {
+                        Some(value) => validate_value(field_schema, value, &field_path)?,
+                        None => return Err(format!("missing field {}", field_path)),
+                    }
+                }
+                Ok(())
+            }
+            // list endpoints are often described by the shape of one element
+            Value::Array(items) => match items.first() {
+                Some(first) => validate_value(schema, first, &format!("{}[0]", path)),
+                None => Ok(()),
+            },
+            _ => Err(format!("expected an object at {}, got {}", path, body)),
+        },
+        Value::Array(items) => match (items.first(), body) {
+            (Some(item_schema), Value::Array(values)) => match values.first() {
+                Some(first) => validate_value(item_schema, first, &format!("{}[0]", path)),
+                None => Ok(()),
+            },
+            (None, Value::Array(_)) => Ok(()),
+            _ => Err(format!("expected an array at {}, got {}", path, body)),
+        },
+        Value::String(s) => {
+            let name = type_name(s);
+            let ok = if is_number_type(&name) {
+                body.is_number()
+            } else if name == "bool" || name == "boolean" {
+                body.is_boolean()
+            } else if name == "string" || name == "str" {
+                body.is_string()
+            } else {
+                true
+            };
+            if ok {
+                Ok(())
+            } else {
+                Err(format!("expected {} at {}, got {}", name, path, body))
+            }
+        }
+        _ => Ok(()),
+    }
+}
+
+// Fills `{param}` segments from values seen in earlier request and response bodies
+pub fn fill_route(route: &str, known_values: &HashMap<String, Value>) -> String {
+    let mut filled = String::new();
+    let mut rest = route;
+    while let Some(start) = rest.find('{') {
+        let end = match rest[start..].find('}') {
+            Some(end) => start + end,
+            None => break,
+        };
+        filled.push_str(&rest[..start]);
+        let param = &rest[start + 1..end];
+        let value = known_values
+            .get(param)
+            .or(known_values.get("id").filter(|_| param.ends_with("id")))
+            .cloned();
+        let value = match value {
+            Some(Value::String(s)) => s,
+            Some(v) => v.to_string(),
+            None if param.ends_with("id") => "1".to_string(),
+            None => "test".to_string(),
+        };
+        filled.push_str(&value);
+        rest = &rest[end + 1..];
+    }
+    filled.push_str(rest);
+    filled
+}
+
+fn remember_values(body: &Value, known_values: &mut HashMap<String, Value>) {
+    let obj = match body {
+        Value::Object(obj) => obj,
+        Value::Array(items) => match items.first() {
+            Some(Value::Object(obj)) => obj,
+            _ => return,
+        },
+        _ => return,
+    };
+    for (key, value) in obj {
+        if value.is_string() || value.is_number() {
+            known_values.insert(key.clone(), value.clone());
+        }
+    }
+}
+
+// Creates come first so reads and updates have something to work on; deletes go last
+fn method_order(method: &str) -> u8 {
+    match method.to_lowercase().as_str() {
+        "post" => 0,
+        "get" => 1,
+        "put" | "patch" => 2,
+        "delete" => 3,
+        _ => 4,
+    }
+}
+
+// Uses the caller's client so the run keeps a single connection pool
+pub async fn run_contract_tests(client: &Client, base_url: &str, routes: &[RouteObject]) -> Result<ContractReport, reqwest::Error> {
+    let mut ordered: Vec<&RouteObject> = routes.iter().collect();
+    ordered.sort_by_key(|r| method_order(&r.method));
+
+    let mut known_values: HashMap<String, Value> = HashMap::new();
+    let mut report = ContractReport::default();
+    for route in ordered {
+        let url = format!("{}{}", base_url, fill_route(&route.route, &known_values));
+        let method = match Method::from_bytes(route.method.to_uppercase().as_bytes()) {
+            Ok(method) => method,
+            Err(_) => {
+                report.results.push(RouteResult {
+                    method: route.method.clone(),
+                    route: route.route.clone(),
+                    url,
+                    status: None,
+                    passed: false,
+                    message: format!("unknown HTTP method {}", route.method),
+                });
+                continue;
+            }
+        };
+        let mut request = client.request(method, &url).timeout(REQUEST_TIMEOUT);
+        if !is_empty_schema(&route.request_body) {
+            let body = synthesize_value(&route.request_body);
+            remember_values(&body, &mut known_values);
+            request = request.json(&body);
+        }
+        let (status, passed, message) = match request.send().await {
+            Err(e) => (None, false, e.to_string()),
+            Ok(res) => {
+                let status = res.status();
+                let text = res.text().await.unwrap_or_default();
+                if !status.is_success() {
+                    (Some(status.as_u16()), false, format!("expected a 2xx status, body: {}", text))
+                } else if is_empty_schema(&route.response) || !(route.response.is_object() || route.response.is_array()) {
+                    (Some(status.as_u16()), true, "ok".to_string())
+                } else {
+                    match serde_json::from_str::<Value>(&text) {
+                        Ok(body) => {
+                            remember_values(&body, &mut known_values);
+                            match validate_value(&route.response, &body, "response") {
+                                Ok(()) => (Some(status.as_u16()), true, "ok".to_string()),
+                                Err(e) => (Some(status.as_u16()), false, e),
+                            }
+                        }
+                        Err(_) => (Some(status.as_u16()), false, format!("response is not JSON: {}", text)),
+                    }
+                }
+            }
+        };
+        report.results.push(RouteResult {
+            method: route.method.clone(),
+            route: route.route.clone(),
+            url,
+            status,
+            passed,
+            message,
+        });
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_synthesize_and_validate() {
+        let schema = json!({ "id": "number", "name": "string", "completed": "bool", "tags": ["string"] });
+        let value = synthesize_value(&schema);
+        assert_eq!(value, json!({ "id": 1, "name": "test", "completed": true, "tags": ["test"] }));
+        assert!(validate_value(&schema, &value, "response").is_ok());
+        assert!(validate_value(&schema, &json!([value]), "response").is_ok());
+        assert!(validate_value(&schema, &json!({ "id": "1", "name": "x", "completed": true, "tags": [] }), "response").is_err());
+        assert!(validate_value(&schema, &json!({ "id": 1 }), "response").is_err());
+        assert!(validate_value(&json!("None"), &json!("anything"), "response").is_ok());
+    }
+
+    #[test]
+    fn test_fill_route() {
+        let mut known = HashMap::new();
+        assert_eq!(fill_route("/item/{id}", &known), "/item/1");
+        assert_eq!(fill_route("/crypto/{symbol}", &known), "/crypto/test");
+        known.insert("id".to_string(), json!(42));
+        known.insert("symbol".to_string(), json!("BTCUSDT"));
+        assert_eq!(fill_route("/item/{item_id}/price/{symbol}", &known), "/item/42/price/BTCUSDT");
+        assert_eq!(fill_route("/health", &known), "/health");
+    }
+}
@@ -0,0 +1,368 @@
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use crate::{
+    errors::{BotError, BotResult},
+    models::general::{
+        schema::{object_schema, JsonSchema},
+        workspace::Workspace,
+    },
+};
+
+// A crate the model wants in Cargo.toml. An empty version takes the one from the allowlist.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct DependencyRequest {
+    pub name: String,
+    #[serde(default)]
+    pub version: String,
+    #[serde(default)]
+    pub features: Vec<String>,
+}
+
+impl JsonSchema for DependencyRequest {
+    fn json_schema() -> serde_json::Value {
+        object_schema(&[
+            ("name", String::json_schema()),
+            ("version", String::json_schema()),
+            ("features", Vec::<String>::json_schema()),
+        ])
+    }
+}
+
+// Version requirement written to Cargo.toml, and the features that may be enabled (any when unset)
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct AllowedCrate {
+    pub version: String,
+    #[serde(default)]
+    pub features: Option<Vec<String>>,
+}
+
+// The "dependencies" section of the config file. Setting `allowed` replaces the built-in list.
+// With `offline` cargo never touches the network; a `mirror` (a `cargo vendor` directory) is then
+// where crates are resolved from, and they must be present in it. Online the mirror is not used.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct DependencyPolicy {
+    pub allowed: BTreeMap<String, AllowedCrate>,
+    pub mirror: Option<PathBuf>,
+    pub offline: bool,
+}
+
+impl Default for DependencyPolicy {
+    fn default() -> Self {
+        // the crates of the backend template, pinned the same way, plus a few common ones
+        let allowed = [
+            ("actix-cors", "=0.6.4"),
+            ("actix-web", "=4.4.0"),
+            ("anyhow", "^1"),
+            ("argon2", "=0.5.2"),
+            ("async-trait", "=0.1.74"),
+            ("chrono", "^0.4"),
+            ("futures", "^0.3"),
+            ("jsonwebtoken", "=9.2.0"),
+            ("mongodb", "=2.8.2"),
+            ("rand", "^0.8"),
+            ("regex", "^1"),
+            ("reqwest", "=0.11.22"),
+            ("serde", "=1.0.190"),
+            ("serde_json", "=1.0.107"),
+            ("thiserror", "^1"),
+            ("tokio", "=1.33.0"),
+            ("uuid", "^1"),
+        ];
+        Self {
+            allowed: allowed
+                .iter()
+                .map(|(name, version)| (name.to_string(), AllowedCrate { version: version.to_string(), features: None }))
+                .collect(),
+            mirror: None,
+            offline: false,
+        }
+    }
+}
+
+static POLICY: Mutex<Option<DependencyPolicy>> = Mutex::new(None);
+
+pub fn set_dependency_policy(policy: DependencyPolicy) {
+    *POLICY.lock().unwrap_or_else(|e| e.into_inner()) = Some(policy);
+}
+
+pub fn dependency_policy() -> DependencyPolicy {
+    POLICY.lock().unwrap_or_else(|e| e.into_inner()).clone().unwrap_or_default()
+}
+
+type Version = (u64, u64, u64);
+
+// "1", "1.2" or "1.2.3"; pre-release and build metadata are ignored
+fn parse_partial(text: &str) -> Option<(u64, Option<u64>, Option<u64>)> {
+    let text = text.trim().split(['-', '+']).next().unwrap_or_default();
+    let mut parts = text.split('.');
+    let major = parts.next()?.parse().ok()?;
+    let minor = match parts.next() {
+        Some(part) => Some(part.parse().ok()?),
+        None => None,
+    };
+    let patch = match parts.next() {
+        Some(part) => Some(part.parse().ok()?),
+        None => None,
+    };
+    if parts.next().is_some() {
+        return None;
+    }
+    Some((major, minor, patch))
+}
+
+fn comparator_matches(comparator: &str, version: Version) -> Option<bool> {
+    let comparator = comparator.trim();
+    if comparator == "*" {
+        return Some(true);
+    }
+    let (op, rest) = [">=", "<=", ">", "<", "=", "^", "~"]
+        .iter()
+        .find_map(|op| comparator.strip_prefix(op).map(|rest| (*op, rest)))
+        .unwrap_or(("^", comparator));
+    let (major, minor, patch) = parse_partial(rest)?;
+    let lower = (major, minor.unwrap_or(0), patch.unwrap_or(0));
+    Some(match op {
+        // parts left out, as in "=1.0", match any value
+        "=" => version.0 == major && minor.unwrap_or(version.1) == version.1 && patch.unwrap_or(version.2) == version.2,
+        ">=" => version >= lower,
+        ">" => version > lower,
+        "<=" => version <= lower,
+        "<" => version < lower,
+        "~" => version >= lower && version < if minor.is_some() { (major, lower.1 + 1, 0) } else { (major + 1, 0, 0) },
+        // caret: everything up to the next breaking release
+        _ => {
+            let upper = match (major, minor, patch) {
+                (0, Some(0), Some(p)) => (0, 0, p + 1),
+                (0, Some(m), _) if m > 0 || patch.is_none() => (0, m + 1, 0),
+                (0, None, _) => (1, 0, 0),
+                (m, _, _) => (m + 1, 0, 0),
+            };
+            version >= lower && version < upper
+        }
+    })
+}
+
+// Whether `version` satisfies a Cargo version requirement such as "^0.4", "=1.0.190" or ">=1.2, <2"
+pub fn version_matches(requirement: &str, version: &str) -> bool {
+    let Some((major, minor, patch)) = parse_partial(version.trim_start_matches(['=', '^', '~'])) else {
+        return false;
+    };
+    let version = (major, minor.unwrap_or(0), patch.unwrap_or(0));
+    requirement.split(',').all(|c| comparator_matches(c, version).unwrap_or(false))
+}
+
+fn is_feature_name(feature: &str) -> bool {
+    !feature.is_empty() && feature.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '+' | '-'))
+}
+
+fn rejected(name: &str, reason: String) -> BotError {
+    BotError::DependencyRejected { crate_name: name.to_string(), reason }
+}
+
+impl DependencyPolicy {
+    // One line per crate for the prompts
+    pub fn prompt_list(&self) -> String {
+        self.allowed
+            .iter()
+            .map(|(name, allowed)| match &allowed.features {
+                Some(features) => format!("{} {} (features: {})", name, allowed.version, features.join(", ")),
+                None => format!("{} {}", name, allowed.version),
+            })
+            .collect::<Vec<String>>()
+            .join("\n")
+    }
+    // Checks every request before anything is written. The first one that is not allowed, asks
+    // for another version or features than the allowlist permits, or is missing from the
+    // mirror of an offline run, is reported.
+    pub fn check(&self, requests: &[DependencyRequest]) -> BotResult<()> {
+        for request in requests {
+            let Some(allowed) = self.allowed.get(&request.name) else {
+                return Err(rejected(&request.name, "not in the allowed dependencies".to_string()));
+            };
+            if !request.version.trim().is_empty() && !version_matches(&allowed.version, &request.version) {
+                return Err(rejected(&request.name, format!("version {} is outside the allowed {}", request.version, allowed.version)));
+            }
+            // they are written into Cargo.toml as they are
+            if let Some(feature) = request.features.iter().find(|f| !is_feature_name(f)) {
+                return Err(rejected(&request.name, format!("{:?} is not a feature name", feature)));
+            }
+            if let Some(features) = &allowed.features {
+                if let Some(feature) = request.features.iter().find(|f| !features.contains(f)) {
+                    return Err(rejected(&request.name, format!("feature {} is not allowed", feature)));
+                }
+            }
+            if let Some(mirror) = self.mirror.as_ref().filter(|_| self.offline) {
+                if !mirror_has(mirror, &request.name, &allowed.version)? {
+                    return Err(rejected(&request.name, format!("no version matching {} in the mirror {}", allowed.version, mirror.display())));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+// Vendored crates live in directories named `<name>-<version>`
+fn mirror_has(mirror: &Path, name: &str, requirement: &str) -> BotResult<bool> {
+    let prefix = format!("{}-", name);
+    for entry in fs::read_dir(mirror)? {
+        let dir_name = entry?.file_name().to_string_lossy().to_string();
+        if let Some(version) = dir_name.strip_prefix(&prefix) {
+            if version_matches(requirement, version) {
+                return Ok(true);
+            }
+        }
+    }
+    Ok(false)
+}
+
+fn dependency_line(name: &str, version: &str, features: &[String]) -> String {
+    if features.is_empty() {
+        return format!("{} = \"{}\"", name, version);
+    }
+    let features: Vec<String> = features.iter().map(|f| format!("\"{}\"", f)).collect();
+    format!("{} = {{ version = \"{}\", features = [{}] }}", name, version, features.join(", "))
+}
+
+// Features already enabled on a `name = { ..., features = ["a", "b"] }` line
+fn existing_features(line: &str) -> Vec<String> {
+    let Some(start) = line.find("features") else {
+        return vec![];
+    };
+    let list = &line[start..];
+    match (list.find('['), list.find(']')) {
+        (Some(open), Some(close)) if open < close => list[open + 1..close]
+            .split(',')
+            .map(|f| f.trim().trim_matches('"').to_string())
+            .filter(|f| !f.is_empty())
+            .collect(),
+        _ => vec![],
+    }
+}
+
+// Adds the requested crates to [dependencies] of `cargo_toml` at the allowed version, keeping
+// features that were already enabled. Requests must have passed `check`.
+pub fn with_dependencies(cargo_toml: &str, policy: &DependencyPolicy, requests: &[DependencyRequest]) -> String {
+    let mut lines: Vec<String> = cargo_toml.lines().map(String::from).collect();
+    let section = match lines.iter().position(|l| l.trim() == "[dependencies]") {
+        Some(index) => index,
+        None => {
+            lines.push(String::new());
+            lines.push("[dependencies]".to_string());
+            lines.len() - 1
+        }
+    };
+    for request in requests {
+        let Some(allowed) = policy.allowed.get(&request.name) else {
+            continue;
+        };
+        let end = lines[section + 1..]
+            .iter()
+            .position(|l| l.trim_start().starts_with('['))
+            .map(|i| section + 1 + i)
+            .unwrap_or(lines.len());
+        let existing = (section + 1..end).find(|&i| lines[i].split('=').next().map(str::trim) == Some(request.name.as_str()));
+        let mut features = existing.map(|i| existing_features(&lines[i])).unwrap_or_default();
+        for feature in &request.features {
+            if !features.contains(feature) {
+                features.push(feature.clone());
+            }
+        }
+        let line = dependency_line(&request.name, &allowed.version, &features);
+        match existing {
+            Some(i) => lines[i] = line,
+            None => {
+                // after the last dependency, before any blank lines that separate the next section
+                let mut insert_at = end;
+                while insert_at > section + 1 && lines[insert_at - 1].trim().is_empty() {
+                    insert_at -= 1;
+                }
+                lines.insert(insert_at, line);
+            }
+        }
+    }
+    format!("{}\n", lines.join("\n"))
+}
+
+// Writes the requested crates into the workspace Cargo.toml
+pub fn add_dependencies(workspace: &Workspace, policy: &DependencyPolicy, requests: &[DependencyRequest]) -> BotResult<()> {
+    if requests.is_empty() {
+        return Ok(());
+    }
+    let path = workspace.root().join("Cargo.toml");
+    let contents = fs::read_to_string(&path)?;
+    fs::write(&path, with_dependencies(&contents, policy, requests))?;
+    Ok(())
+}
+
+// Points cargo at the mirror and keeps it off the network, through the workspace's own
+// .cargo/config.toml so builds started by hand behave the same
+pub fn write_cargo_config(workspace: &Workspace, policy: &DependencyPolicy) -> BotResult<()> {
+    if !policy.offline {
+        return Ok(());
+    }
+    let mut config = String::from("[net]\noffline = true\n");
+    if let Some(mirror) = &policy.mirror {
+        let mirror = fs::canonicalize(mirror)
+            .map_err(|e| BotError::Config(format!("dependency mirror {}: {}", mirror.display(), e)))?;
+        config.push_str(&format!(
+            "\n[source.crates-io]\nreplace-with = \"hannah-mirror\"\n\n[source.hannah-mirror]\ndirectory = {:?}\n",
+            mirror.to_string_lossy()
+        ));
+    }
+    let dir = workspace.root().join(".cargo");
+    fs::create_dir_all(&dir)?;
+    fs::write(dir.join("config.toml"), config)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(name: &str, version: &str, features: &[&str]) -> DependencyRequest {
+        DependencyRequest { name: name.to_string(), version: version.to_string(), features: features.iter().map(|f| f.to_string()).collect() }
+    }
+
+    #[test]
+    fn test_version_requirements() {
+        assert!(version_matches("^0.4", "0.4.31"));
+        assert!(!version_matches("^0.4", "0.5.0"));
+        assert!(version_matches("^1", "1.6.1"));
+        assert!(!version_matches("^1", "2.0.0"));
+        assert!(version_matches("=1.0.190", "1.0.190"));
+        assert!(!version_matches("=1.0.190", "1.0.191"));
+        assert!(version_matches("~1.2", "1.2.9"));
+        assert!(!version_matches("~1.2", "1.3.0"));
+        assert!(version_matches(">=1.2, <2", "1.9.0"));
+        assert!(version_matches("^0.0.3", "0.0.3"));
+        assert!(!version_matches("^0.0.3", "0.0.4"));
+        assert!(!version_matches("^1", "latest"));
+    }
+
+    #[test]
+    fn test_allowed_dependencies_are_added_to_cargo_toml() {
+        let mut policy = DependencyPolicy::default();
+        policy.allowed.insert("chrono".to_string(), AllowedCrate { version: "^0.4".to_string(), features: Some(vec!["serde".to_string()]) });
+        assert!(policy.check(&[request("chrono", "0.4", &["serde"]), request("uuid", "", &["v4"])]).is_ok());
+        // models often leave out what they do not need
+        let bare: DependencyRequest = serde_json::from_str(r#"{"name": "uuid"}"#).unwrap();
+        assert_eq!(bare, request("uuid", "", &[]));
+        for bad in [request("openssl", "", &[]), request("chrono", "0.3", &[]), request("chrono", "", &["unstable-locales"]), request("uuid", "", &["v4\"] }\nopenssl = { version = \"*"])] {
+            assert!(matches!(policy.check(&[bad]), Err(BotError::DependencyRejected { .. })));
+        }
+
+        let cargo_toml = "[package]\nname = \"app\"\n\n[dependencies]\nserde = \"=1.0.190\"\nuuid = { version = \"^1\", features = [\"serde\"] }\n\n[dev-dependencies]\n";
+        let updated = with_dependencies(cargo_toml, &policy, &[request("chrono", "", &["serde"]), request("uuid", "", &["v4"])]);
+        assert_eq!(
+            updated,
+            "[package]\nname = \"app\"\n\n[dependencies]\nserde = \"=1.0.190\"\nuuid = { version = \"^1\", features = [\"serde\", \"v4\"] }\nchrono = { version = \"^0.4\", features = [\"serde\"] }\n\n[dev-dependencies]\n"
+        );
+    }
+}
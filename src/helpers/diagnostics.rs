@@ -0,0 +1,295 @@
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Component, Path, PathBuf},
+    process::{Command, Stdio},
+};
+
+use crate::errors::BotResult;
+
+// Shape of `cargo ... --message-format=json` output, only the parts we use
+#[derive(Deserialize)]
+struct CargoLine {
+    reason: String,
+    message: Option<RustcMessage>,
+}
+
+#[derive(Deserialize)]
+struct RustcMessage {
+    message: String,
+    code: Option<RustcCode>,
+    level: String,
+    spans: Vec<RustcSpan>,
+    children: Vec<RustcMessage>,
+    rendered: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct RustcCode {
+    code: String,
+}
+
+#[derive(Deserialize)]
+struct RustcSpan {
+    file_name: String,
+    byte_start: usize,
+    byte_end: usize,
+    line_start: usize,
+    line_end: usize,
+    column_start: usize,
+    is_primary: bool,
+    suggested_replacement: Option<String>,
+    suggestion_applicability: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Suggestion {
+    pub file: String,
+    pub byte_start: usize,
+    pub byte_end: usize,
+    pub replacement: String,
+    pub machine_applicable: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub code: Option<String>,
+    pub level: String,
+    pub message: String,
+    pub file: Option<String>,
+    pub line: usize,
+    pub end_line: usize,
+    pub column: usize,
+    pub suggestions: Vec<Suggestion>,
+    pub rendered: String,
+}
+
+impl Diagnostic {
+    pub fn is_error(&self) -> bool {
+        self.level == "error"
+    }
+}
+
+fn collect_suggestions(message: &RustcMessage, suggestions: &mut Vec<Suggestion>) {
+    for span in &message.spans {
+        if let Some(replacement) = &span.suggested_replacement {
+            suggestions.push(Suggestion {
+                file: span.file_name.clone(),
+                byte_start: span.byte_start,
+                byte_end: span.byte_end,
+                replacement: replacement.clone(),
+                machine_applicable: span.suggestion_applicability.as_deref() == Some("MachineApplicable"),
+            });
+        }
+    }
+    for child in &message.children {
+        collect_suggestions(child, suggestions);
+    }
+}
+
+// Turns cargo's JSON lines into diagnostics. Lines that are not compiler messages are skipped,
+// as are summary messages like "aborting due to 2 previous errors" which carry no span.
+pub fn parse_cargo_messages(stdout: &str) -> Vec<Diagnostic> {
+    let mut diagnostics = vec![];
+    for line in stdout.lines() {
+        let parsed: CargoLine = match serde_json::from_str(line) {
+            Ok(parsed) => parsed,
+            Err(_) => continue,
+        };
+        if parsed.reason != "compiler-message" {
+            continue;
+        }
+        let message = match parsed.message {
+            Some(message) => message,
+            None => continue,
+        };
+        let primary = message.spans.iter().find(|s| s.is_primary).or(message.spans.first());
+        if primary.is_none() && message.code.is_none() {
+            continue;
+        }
+        let mut suggestions = vec![];
+        collect_suggestions(&message, &mut suggestions);
+        diagnostics.push(Diagnostic {
+            code: message.code.as_ref().map(|c| c.code.clone()),
+            level: message.level.clone(),
+            message: message.message.clone(),
+            file: primary.map(|s| s.file_name.clone()),
+            line: primary.map(|s| s.line_start).unwrap_or(0),
+            end_line: primary.map(|s| s.line_end).unwrap_or(0),
+            column: primary.map(|s| s.column_start).unwrap_or(0),
+            suggestions,
+            rendered: message.rendered.clone().unwrap_or(message.message.clone()),
+        });
+    }
+    diagnostics
+}
+
+pub struct CargoOutcome {
+    pub success: bool,
+    pub diagnostics: Vec<Diagnostic>,
+    // JSON lines interleaved with whatever the build ran, e.g. libtest's report under `cargo test`
+    pub stdout: String,
+    pub stderr: String,
+}
+
+// Runs e.g. `cargo build --message-format=json` in `root`. Arguments after a `--` are for the
+// tool cargo runs (`cargo clippy -- -D ...`) and stay behind the message format flag.
+pub fn run_cargo_with_diagnostics(root: &Path, args: &[&str]) -> BotResult<CargoOutcome> {
+    let (cargo_args, tool_args) = args.split_at(args.iter().position(|a| *a == "--").unwrap_or(args.len()));
+    let output = Command::new("cargo")
+        .args(cargo_args)
+        .arg("--message-format=json")
+        .args(tool_args)
+        .current_dir(root)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .output()?;
+    let stdout = String::from_utf8_lossy(&output.stdout).to_string();
+    Ok(CargoOutcome {
+        success: output.status.success(),
+        diagnostics: parse_cargo_messages(&stdout),
+        stdout,
+        stderr: String::from_utf8_lossy(&output.stderr).to_string(),
+    })
+}
+
+// Where a span's file is in the workspace. Spans can point at dependencies in the cargo registry
+// or, through symlinks, anywhere else; those are not ours to edit.
+fn file_in_root(root: &Path, file: &str) -> Option<PathBuf> {
+    let relative = Path::new(file);
+    if !relative.components().all(|c| matches!(c, Component::Normal(_) | Component::CurDir)) {
+        return None;
+    }
+    let path = fs::canonicalize(root.join(relative)).ok()?;
+    path.starts_with(fs::canonicalize(root).ok()?).then_some(path)
+}
+
+// Applies every machine-applicable suggestion inside `root`. Edits are applied back to front per file and
+// overlapping edits are skipped, so byte offsets stay valid. Returns how many were applied.
+pub fn apply_machine_applicable(root: &Path, diagnostics: &[Diagnostic]) -> BotResult<usize> {
+    let mut by_file: HashMap<String, Vec<&Suggestion>> = HashMap::new();
+    for suggestion in diagnostics.iter().flat_map(|d| d.suggestions.iter()) {
+        if suggestion.machine_applicable {
+            by_file.entry(suggestion.file.clone()).or_default().push(suggestion);
+        }
+    }
+    let mut applied = 0;
+    for (file, mut suggestions) in by_file {
+        let Some(path) = file_in_root(root, &file) else {
+            continue;
+        };
+        let mut contents = fs::read_to_string(&path)?;
+        suggestions.sort_by_key(|s| std::cmp::Reverse(s.byte_start));
+        suggestions.dedup();
+        let mut last_start = usize::MAX;
+        for suggestion in suggestions {
+            if suggestion.byte_end > last_start
+                || suggestion.byte_end > contents.len()
+                || !contents.is_char_boundary(suggestion.byte_start)
+                || !contents.is_char_boundary(suggestion.byte_end)
+            {
+                continue;
+            }
+            contents.replace_range(suggestion.byte_start..suggestion.byte_end, &suggestion.replacement);
+            last_start = suggestion.byte_start;
+            applied += 1;
+        }
+        fs::write(&path, contents)?;
+    }
+    Ok(applied)
+}
+
+// The lines around a diagnostic, numbered, with the offending lines marked
+pub fn code_snippet(source: &str, line: usize, end_line: usize, context: usize) -> String {
+    let lines: Vec<&str> = source.lines().collect();
+    if line == 0 || lines.is_empty() {
+        return String::new();
+    }
+    let first = line.saturating_sub(context).max(1);
+    let last = (end_line.max(line) + context).min(lines.len());
+    (first..=last)
+        .map(|n| {
+            let marker = if n >= line && n <= end_line.max(line) { ">" } else { " " };
+            format!("{} {:>4} | {}", marker, n, lines[n - 1])
+        })
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+// Error diagnostics with code snippets, ready to go into a repair prompt
+pub fn format_for_llm(root: &Path, diagnostics: &[Diagnostic]) -> String {
+    let mut sources: HashMap<String, String> = HashMap::new();
+    diagnostics
+        .iter()
+        .filter(|d| d.is_error())
+        .map(|d| {
+            let code = d.code.clone().map(|c| format!("[{}]", c)).unwrap_or_default();
+            let mut entry = format!("error{}: {}", code, d.message);
+            if let Some(file) = &d.file {
+                let source = sources
+                    .entry(file.clone())
+                    .or_insert_with(|| fs::read_to_string(root.join(file)).unwrap_or_default());
+                entry.push_str(&format!("\n  --> {}:{}:{}\n{}", file, d.line, d.column, code_snippet(source, d.line, d.end_line, 3)));
+            }
+            for suggestion in d.suggestions.iter().filter(|s| !s.machine_applicable) {
+                entry.push_str(&format!("\n  help: consider `{}`", suggestion.replacement));
+            }
+            entry
+        })
+        .collect::<Vec<String>>()
+        .join("\n\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const UNUSED_MUT: &str = r#"{"reason":"compiler-message","package_id":"app 0.1.0","message":{"rendered":"warning: variable does not need to be mutable","children":[{"children":[],"code":null,"level":"help","message":"remove this `mut`","rendered":null,"spans":[{"byte_end":24,"byte_start":20,"column_end":13,"column_start":9,"expansion":null,"file_name":"src/main.rs","is_primary":true,"label":null,"line_end":2,"line_start":2,"suggested_replacement":"","suggestion_applicability":"MachineApplicable","text":[]}]}],"code":{"code":"unused_mut","explanation":null},"level":"warning","message":"variable does not need to be mutable","spans":[{"byte_end":25,"byte_start":20,"column_end":14,"column_start":9,"expansion":null,"file_name":"src/main.rs","is_primary":true,"label":null,"line_end":2,"line_start":2,"suggested_replacement":null,"suggestion_applicability":null,"text":[]}]}}
+{"reason":"compiler-message","package_id":"app 0.1.0","message":{"rendered":"error[E0425]: cannot find value `y` in this scope","children":[],"code":{"code":"E0425","explanation":null},"level":"error","message":"cannot find value `y` in this scope","spans":[{"byte_end":45,"byte_start":44,"column_end":15,"column_start":14,"expansion":null,"file_name":"src/main.rs","is_primary":true,"label":null,"line_end":3,"line_start":3,"suggested_replacement":null,"suggestion_applicability":null,"text":[]}]}}
+{"reason":"compiler-message","package_id":"app 0.1.0","message":{"rendered":"error: aborting due to 1 previous error","children":[],"code":null,"level":"error","message":"aborting due to 1 previous error","spans":[]}}
+{"reason":"build-finished","success":false}"#;
+
+    #[test]
+    fn test_parse_cargo_messages() {
+        let diagnostics = parse_cargo_messages(UNUSED_MUT);
+        assert_eq!(diagnostics.len(), 2);
+        assert_eq!(diagnostics[0].code.as_deref(), Some("unused_mut"));
+        assert!(diagnostics[0].suggestions[0].machine_applicable);
+        assert!(diagnostics[1].is_error());
+        assert_eq!(diagnostics[1].code.as_deref(), Some("E0425"));
+        assert_eq!((diagnostics[1].line, diagnostics[1].column), (3, 14));
+    }
+
+    #[test]
+    fn test_apply_machine_applicable_and_format() {
+        let root = std::env::temp_dir().join(format!("hannah_diagnostics_{}", std::process::id()));
+        fs::create_dir_all(root.join("src")).unwrap();
+        fs::write(root.join("src/main.rs"), "fn main() {\n    let mut x = 1;\n    println!(y);\n}\n").unwrap();
+
+        let mut diagnostics = parse_cargo_messages(UNUSED_MUT);
+        // the same fix in a file outside the workspace is left alone
+        let outside = root.with_extension("outside.rs");
+        fs::write(&outside, "fn main() {\n    let mut x = 1;\n}\n").unwrap();
+        let sibling = format!("../{}", outside.file_name().unwrap().to_string_lossy());
+        for file in [outside.display().to_string(), sibling] {
+            let mut escaping = diagnostics[0].clone();
+            escaping.suggestions.iter_mut().for_each(|s| s.file = file.clone());
+            diagnostics.push(escaping);
+        }
+        assert_eq!(apply_machine_applicable(&root, &diagnostics).unwrap(), 1);
+        assert_eq!(fs::read_to_string(&outside).unwrap(), "fn main() {\n    let mut x = 1;\n}\n");
+        assert_eq!(
+            fs::read_to_string(root.join("src/main.rs")).unwrap(),
+            "fn main() {\n    let x = 1;\n    println!(y);\n}\n"
+        );
+
+        let prompt = format_for_llm(&root, &diagnostics);
+        assert!(prompt.starts_with("error[E0425]: cannot find value `y` in this scope"));
+        assert!(prompt.contains(">    3 |     println!(y);"));
+        assert!(!prompt.contains("unused_mut"));
+
+        fs::remove_dir_all(&root).unwrap();
+        fs::remove_file(&outside).unwrap();
+    }
+}
@@ -0,0 +1,257 @@
+use serde::de::DeserializeOwned;
+
+// Pulls code out of an LLM completion. Fenced blocks win (the longest one, since models like to
+// add a short usage example after the real code); an unterminated fence from a truncated answer
+// keeps everything after it. Without fences the whole answer is the code.
+pub fn extract_code(text: &str) -> String {
+    let mut blocks: Vec<String> = vec![];
+    let mut current: Option<Vec<&str>> = None;
+    for line in text.lines() {
+        if line.trim_start().starts_with("```") {
+            match current.take() {
+                Some(block) => blocks.push(block.join("\n")),
+                None => current = Some(vec![]),
+            }
+            continue;
+        }
+        if let Some(block) = current.as_mut() {
+            block.push(line);
+        }
+    }
+    if let Some(block) = current {
+        blocks.push(block.join("\n"));
+    }
+    match blocks.into_iter().max_by_key(|b| b.len()) {
+        Some(block) => format!("{}\n", block.trim_end()),
+        None => format!("{}\n", text.trim()),
+    }
+}
+
+// Byte range of the first balanced JSON object or array in the text
+pub fn find_json_value(text: &str) -> Option<&str> {
+    let start = text.find(['{', '['])?;
+    let mut depth = 0usize;
+    let mut quote: Option<char> = None;
+    let mut escaped = false;
+    for (offset, c) in text[start..].char_indices() {
+        if let Some(q) = quote {
+            if escaped {
+                escaped = false;
+            } else if c == '\\' {
+                escaped = true;
+            } else if c == q {
+                quote = None;
+            }
+            continue;
+        }
+        match c {
+            '"' | '\'' => quote = Some(c),
+            '{' | '[' => depth += 1,
+            '}' | ']' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(&text[start..start + offset + c.len_utf8()]);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+#[derive(PartialEq)]
+enum Container {
+    Object { expect_key: bool },
+    Array,
+}
+
+// Fixes the defects models commonly produce: single quoted strings, trailing commas, missing
+// commas between members, `//` comments and Python style True/False/None.
+pub fn repair_json(text: &str) -> String {
+    let chars: Vec<char> = text.chars().collect();
+    let mut out = String::with_capacity(text.len());
+    let mut stack: Vec<Container> = vec![];
+    let mut last_was_value = false;
+    let mut i = 0;
+
+    // called before anything that starts a new key or value
+    fn separate(out: &mut String, stack: &mut [Container], last_was_value: &mut bool) {
+        if *last_was_value && !stack.is_empty() {
+            out.push(',');
+            if let Some(Container::Object { expect_key }) = stack.last_mut() {
+                *expect_key = true;
+            }
+        }
+        *last_was_value = false;
+    }
+    fn drop_trailing_comma(out: &mut String) {
+        let trimmed_len = out.trim_end().len();
+        if out[..trimmed_len].ends_with(',') {
+            out.replace_range(trimmed_len - 1..trimmed_len, "");
+        }
+    }
+
+    while i < chars.len() {
+        let c = chars[i];
+        match c {
+            '"' | '\'' => {
+                separate(&mut out, &mut stack, &mut last_was_value);
+                let is_key = matches!(stack.last(), Some(Container::Object { expect_key: true }));
+                out.push('"');
+                i += 1;
+                while i < chars.len() && chars[i] != c {
+                    if chars[i] == '\\' && i + 1 < chars.len() {
+                        // \' is not a valid JSON escape
+                        if chars[i + 1] != '\'' {
+                            out.push('\\');
+                        }
+                        out.push(chars[i + 1]);
+                        i += 2;
+                        continue;
+                    }
+                    if chars[i] == '"' {
+                        out.push('\\');
+                    }
+                    out.push(chars[i]);
+                    i += 1;
+                }
+                out.push('"');
+                if is_key {
+                    if let Some(Container::Object { expect_key }) = stack.last_mut() {
+                        *expect_key = false;
+                    }
+                } else {
+                    last_was_value = true;
+                }
+            }
+            '{' | '[' => {
+                separate(&mut out, &mut stack, &mut last_was_value);
+                stack.push(if c == '{' { Container::Object { expect_key: true } } else { Container::Array });
+                out.push(c);
+            }
+            '}' | ']' => {
+                drop_trailing_comma(&mut out);
+                stack.pop();
+                out.push(c);
+                last_was_value = true;
+            }
+            ',' => {
+                if last_was_value {
+                    out.push(',');
+                }
+                if let Some(Container::Object { expect_key }) = stack.last_mut() {
+                    *expect_key = true;
+                }
+                last_was_value = false;
+            }
+            ':' => {
+                out.push(':');
+                last_was_value = false;
+            }
+            '/' if chars.get(i + 1) == Some(&'/') => {
+                while i < chars.len() && chars[i] != '\n' {
+                    i += 1;
+                }
+                continue;
+            }
+            c if c.is_whitespace() => out.push(c),
+            _ => {
+                // numbers and bare words
+                let start = i;
+                while i < chars.len() && !matches!(chars[i], ',' | ':' | '}' | ']' | '"' | '\'' | '{' | '[') && !chars[i].is_whitespace() {
+                    i += 1;
+                }
+                let word: String = chars[start..i].iter().collect();
+                let word = match word.as_str() {
+                    "True" => "true".to_string(),
+                    "False" => "false".to_string(),
+                    "None" => "null".to_string(),
+                    // placeholder lines such as `...` carry nothing
+                    w if w.chars().all(|c| c == '.') => String::new(),
+                    _ => word,
+                };
+                if !word.is_empty() {
+                    separate(&mut out, &mut stack, &mut last_was_value);
+                    out.push_str(&word);
+                    last_was_value = true;
+                }
+                continue;
+            }
+        }
+        i += 1;
+    }
+    out
+}
+
+// Tries progressively harder to turn a completion into T: as is, without code fences, the first
+// balanced JSON value, then that value repaired. Returns the error of the last attempt.
+pub fn extract_json<T: DeserializeOwned>(text: &str) -> Result<T, String> {
+    if let Ok(value) = serde_json::from_str::<T>(text.trim()) {
+        return Ok(value);
+    }
+    let unfenced = extract_code(text);
+    let candidate = find_json_value(&unfenced).unwrap_or(unfenced.trim());
+    if let Ok(value) = serde_json::from_str::<T>(candidate) {
+        return Ok(value);
+    }
+    serde_json::from_str::<T>(&repair_json(candidate)).map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::agents::agent_traits::{ProjectScope, RouteObject};
+
+    #[test]
+    fn test_extract_code_from_fences() {
+        let text = "Here is the code:\n```rust\nfn main() {\n    println!(\"hi\");\n}\n```\nRun it with `cargo run`.\n```sh\ncargo run\n```";
+        assert_eq!(extract_code(text), "fn main() {\n    println!(\"hi\");\n}\n");
+        assert_eq!(extract_code("fn main() {}"), "fn main() {}\n");
+        assert_eq!(extract_code("```rust\nfn main() {\n// truncated"), "fn main() {\n// truncated\n");
+    }
+
+    #[test]
+    fn test_extract_json_with_preamble_and_fences() {
+        let text = "Sure! Here is the scope:\n```json\n{\"is_crud_required\": true, \"is_user_login_and_logout\": false, \"is_external_urls_required\": false}\n```";
+        let scope: ProjectScope = extract_json(text).unwrap();
+        assert!(scope.is_crud_required);
+        let urls: Vec<String> = extract_json("The urls are ['https://a.com', 'https://b.com',] enjoy").unwrap();
+        assert_eq!(urls, vec!["https://a.com", "https://b.com"]);
+    }
+
+    #[test]
+    fn test_repair_json_route_objects() {
+        // the print_rest_api_endpoints example itself has missing and trailing commas
+        let text = r#"[
+  {
+    "route": "/item/{id}",
+    "is_route_dynamic": true,
+    "method": "get"
+    "request_body": "None",
+    "response": {
+      "id": "number",
+      "completed": "bool",
+    }
+  },
+  {
+    'route': '/crypto',
+    'is_route_dynamic': 'false',
+    'method': 'get',
+    'request_body': None,
+    'response': 'not_provided'
+  },
+  ... // etc
+]"#;
+        let routes: Vec<RouteObject> = extract_json(text).unwrap();
+        assert_eq!(routes.len(), 2);
+        assert_eq!(routes[0].is_route_dynamic, "true");
+        assert_eq!(routes[0].method, "get");
+        assert_eq!(routes[1].route, "/crypto");
+        assert_eq!(routes[1].is_route_dynamic, "false");
+    }
+
+    #[test]
+    fn test_extract_json_reports_error() {
+        assert!(extract_json::<ProjectScope>("I cannot help with that.").is_err());
+    }
+}
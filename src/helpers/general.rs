@@ -1,13 +1,10 @@
 use std::fs;
 
-use reqwest::Client;
 use serde::de::DeserializeOwned;
-use crate::{models::general::llm::Message, apis::call_request::call_gpt};
-use super::command_line::PrintCommand;
+use crate::{models::{agent_basic::basic_agent::BasicAgent, general::{llm::Message, schema::{JsonSchema, OutputSchema}, usage::{check_budget, record_usage}, workspace::Workspace}}, apis::{call_request::call_gpt, client::client_context}, errors::{BotError, BotResult}};
+use super::{cargo_test::TestReport, command_line::PrintCommand, contract_test::ContractReport, dependencies::{add_dependencies, dependency_policy}, extract::{extract_code, extract_json}, history::{commit_revision, Revision}, manifest::FileManifest, patch::EditPlan, scaffold::MAIN_RS_TEMPLATE};
 
 
-const CODE_TEMPLATE_PATH: &str = "/home/ssa006/data_sync/src/gpt_created.rs";
-
 pub fn extend_ai_functions(ai_func: fn(&str) -> &'static str, func_input: &str) -> Message {
     let ai_func_output = ai_func(func_input);
     let msg = format!("FUNCTION {} 
@@ -16,59 +13,181 @@ pub fn extend_ai_functions(ai_func: fn(&str) -> &'static str, func_input: &str)
     Print out what the function will return.", ai_func_output, func_input);
     Message { role: "system".to_string() , content: msg }
 }
-pub async fn ai_task_request(msg_context: String, agent_position: &str, agent_operation: &str, function_pass: fn(&str) -> &'static str) -> String {
-    let func_message = extend_ai_functions(function_pass, &msg_context);
-    PrintCommand::AICall.print_agent_message(agent_position, agent_operation);
-    let llm_response_res = call_gpt(vec![func_message.clone()]).await;
-    match llm_response_res {
-        Ok(r) => r,
-        Err(_) => {
-            call_gpt(vec![func_message]).await.expect("Failed to call GPT4")
+// Sends the function prompt after as much of the agent's earlier conversation as fits the model's
+// context window, then adds both prompt and reply to the agent's memory
+pub async fn ai_task_request(msg_context: String, agent: &mut BasicAgent, agent_operation: &str, function_pass: fn(&str) -> &'static str) -> BotResult<String> {
+    ai_request(msg_context, agent, agent_operation, function_pass, None).await
+}
+async fn ai_request(msg_context: String, agent: &mut BasicAgent, agent_operation: &str, function_pass: fn(&str) -> &'static str, schema: Option<&OutputSchema>) -> BotResult<String> {
+    check_budget()?;
+    let mut func_message = extend_ai_functions(function_pass, &msg_context);
+    let context = client_context()?;
+    let settings = context.models.settings_for(&agent.position, agent_operation);
+    let model = context.provider.model_for(&settings);
+    let mut messages = agent.history_for(&model, &func_message, settings.history_tokens);
+    // after a conversation the prompt has to be the final user turn (Anthropic rejects a trailing assistant turn)
+    if !messages.is_empty() {
+        func_message.role = "user".to_string();
+    }
+    messages.push(func_message.clone());
+    PrintCommand::AICall.print_agent_message(&agent.position, agent_operation);
+    // retries and rate limiting happen in the provider; Ctrl-C drops the request
+    let on_token = |token: &str| PrintCommand::AICall.print_stream_token(token);
+    let llm_response = tokio::select! {
+        res = call_gpt(messages, &settings, schema, &on_token) => res,
+        _ = tokio::signal::ctrl_c() => Err(BotError::UserAbort(format!("interrupted during {}", agent_operation))),
+    };
+    PrintCommand::AICall.end_stream();
+    let llm_response = llm_response?;
+    if let Some(usage) = &llm_response.usage {
+        record_usage(&agent.position, agent_operation, llm_response.model.as_deref().unwrap_or("unknown"), usage);
+    }
+    match llm_response.choices.first() {
+        Some(choice) => {
+            agent.remember(&func_message, &choice.message.content);
+            Ok(choice.message.content.clone())
         }
-    }.choices[0].message.content.clone()
+        None => Err(BotError::LlmDecode {
+            ai_function: agent_operation.to_string(),
+            message: "response contained no choices".to_string(),
+            raw: String::new(),
+        }),
+    }
+}
+// How many times a completion that does not parse is sent back to the model with the error
+const MAX_DECODE_ATTEMPTS: u8 = 3;
+
+// The schema of T goes to the provider as well, so models with structured output cannot answer
+// with anything else. The parse check and retries remain for the ones without it.
+pub async fn ai_task_request_decoded<T: DeserializeOwned + JsonSchema>(msg_context: String, agent: &mut BasicAgent, agent_operation: &str, function_pass: fn(&str) -> &'static str) -> BotResult<T> {
+    let schema = OutputSchema::of::<T>(agent_operation);
+    let mut context = msg_context.clone();
+    let mut attempt = 1;
+    loop {
+        let llm_res = ai_request(context, agent, agent_operation, function_pass, Some(&schema)).await?;
+        match extract_json::<T>(&llm_res) {
+            Ok(value) => return Ok(value),
+            Err(e) if attempt >= MAX_DECODE_ATTEMPTS => {
+                return Err(BotError::LlmDecode {
+                    ai_function: agent_operation.to_string(),
+                    message: e,
+                    raw: llm_res,
+                })
+            }
+            Err(e) => {
+                PrintCommand::Issue.print_agent_message(&agent.position, &format!("Could not parse the response ({}), asking again", e));
+                context = format!("{}\nYOUR PREVIOUS OUTPUT COULD NOT BE PARSED AS JSON: {}\nPREVIOUS OUTPUT: {}\nPrint ONLY the valid JSON value.", msg_context, e, llm_res);
+                attempt += 1;
+            }
+        }
+    }
 }
-pub async fn ai_task_request_decoded<T: DeserializeOwned>(msg_context: String, agent_position: &str, agent_operation: &str, function_pass: fn(&str) -> &'static str) -> T {
-    let llm_res = ai_task_request(msg_context, agent_position, agent_operation, function_pass).await;
-    let llm_res_decoded: T = serde_json::from_str(&llm_res).expect("Failed to decode LLM response");
-    llm_res_decoded
+// Same as ai_task_request but strips code fences and commentary around the code
+pub async fn ai_task_request_code(msg_context: String, agent: &mut BasicAgent, agent_operation: &str, function_pass: fn(&str) -> &'static str) -> BotResult<String> {
+    let llm_res = ai_task_request(msg_context, agent, agent_operation, function_pass).await?;
+    Ok(extract_code(&llm_res))
 }
 // check if req url is valid
-pub async fn check_status_code(url: &str) -> Result<u16, reqwest::Error> {
-    let client = Client::builder().timeout(std::time::Duration::from_secs(5)).build()?;
-    let res = client.get(url).send().await;
+pub async fn check_status_code(url: &str) -> BotResult<u16> {
+    let context = client_context()?;
+    let res = context.http().get(url).timeout(std::time::Duration::from_secs(5)).send().await;
 
     // let res = reqwest::get(url).await  ;
     match res {
         Ok(r) => Ok(r.status().as_u16()),
-        Err(e) => Err(e)
+        Err(e) => Err(e.into())
     }
 }
-// Get code template
-pub fn read_code_template() -> String {
-    fs::read_to_string(String::from(CODE_TEMPLATE_PATH)).expect("Failed to read code template")
+// Get code template: the configured file if there is one, the built-in template otherwise
+pub fn read_code_template(workspace: &Workspace) -> BotResult<String> {
+    if !workspace.template_path.is_file() {
+        return Ok(MAIN_RS_TEMPLATE.to_string());
+    }
+    Ok(fs::read_to_string(&workspace.template_path)?)
 }
 
-pub fn read_exec_main_contents() -> String {
-    fs::read_to_string(String::from("/home/ssa006/data_sync/src/main.rs")).expect("Failed to read exec main contents")
+// Every backend source file plus Cargo.toml, in the FILE: blocks the prompts use
+pub fn read_backend_project(workspace: &Workspace) -> BotResult<String> {
+    Ok(FileManifest::read_project(workspace)?.render())
 }
 
-// Save new backend code
-pub fn save_backend_code(contents: &str) {
-    fs::write(String::from("/home/ssa006/data_sync/src/main.rs"), contents).expect("Failed to write new backend code");
+// Write the generated backend files and crates and commit them to the workspace history. Crates
+// are checked against the allowlist before anything is written.
+pub fn save_backend_files(workspace: &Workspace, manifest: &FileManifest, revision: &Revision) -> BotResult<()> {
+    let policy = dependency_policy();
+    policy.check(&manifest.dependencies)?;
+    manifest.write(workspace)?;
+    add_dependencies(workspace, &policy, &manifest.dependencies)?;
+    commit_revision(workspace.root(), revision)?;
+    Ok(())
+}
+// Apply generated edits and crates to the backend and commit the result. A plan that conflicts
+// or asks for a crate that is not allowed changes nothing.
+pub fn save_backend_edits(workspace: &Workspace, plan: &EditPlan, revision: &Revision) -> BotResult<()> {
+    let policy = dependency_policy();
+    policy.check(&plan.dependencies)?;
+    plan.apply(workspace)?;
+    add_dependencies(workspace, &policy, &plan.dependencies)?;
+    commit_revision(workspace.root(), revision)?;
+    Ok(())
+}
+// Save static frontend client and commit it to the workspace history
+pub fn save_frontend_code(workspace: &Workspace, contents: &str, revision: &Revision) -> BotResult<()> {
+    if let Some(parent) = workspace.frontend_file.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(&workspace.frontend_file, contents)?;
+    commit_revision(workspace.root(), revision)?;
+    Ok(())
+}
+// Save the generated integration tests and declare their module in the main file, which the
+// backend developer may have rewritten without it
+pub fn save_test_code(workspace: &Workspace, contents: &str, revision: &Revision) -> BotResult<()> {
+    fs::write(workspace.api_tests_path(), contents)?;
+    declare_test_module(workspace)?;
+    commit_revision(workspace.root(), revision)?;
+    Ok(())
+}
+pub fn declare_test_module(workspace: &Workspace) -> BotResult<()> {
+    let main = fs::read_to_string(&workspace.main_file)?;
+    if !main.contains("mod api_tests;") {
+        fs::write(&workspace.main_file, format!("{}\n#[cfg(test)]\nmod api_tests;\n", main.trim_end()))?;
+    }
+    Ok(())
+}
+// Save per test results of the integration tests
+pub fn save_test_report(workspace: &Workspace, report: &TestReport) -> BotResult<()> {
+    let contents = serde_json::to_string_pretty(report)
+        .map_err(|e| BotError::Config(format!("could not serialize test report: {}", e)))?;
+    fs::create_dir_all(&workspace.artifact_dir)?;
+    fs::write(workspace.artifact_dir.join("test_report.json"), contents)?;
+    Ok(())
+}
+// Save per route results of the API contract tests
+pub fn save_contract_report(workspace: &Workspace, report: &ContractReport) -> BotResult<()> {
+    let contents = serde_json::to_string_pretty(report)
+        .map_err(|e| BotError::Config(format!("could not serialize contract report: {}", e)))?;
+    fs::create_dir_all(&workspace.artifact_dir)?;
+    fs::write(workspace.artifact_dir.join("contract_report.json"), contents)?;
+    Ok(())
 }
 // Save Json api Endpoint Schema
-pub fn save_api_endpoints(contents: &str) {
-    fs::write(String::from("/home/ssa006/data_sync/src/api_endpoints.json"), contents).expect("Failed to write new api endpoints");
+pub fn save_api_endpoints(workspace: &Workspace, contents: &str) -> BotResult<()> {
+    fs::create_dir_all(&workspace.artifact_dir)?;
+    fs::write(workspace.api_endpoints_path(), contents)?;
+    Ok(())
 }
 #[cfg(test)]
 mod test {
-    use super::{extend_ai_functions, ai_task_request};
+    use super::extend_ai_functions;
     use crate::ai_functions::aifunc_managing::convert_user_input_to_goal;
 
-    #[tokio::test]
-    async fn test_extend_ai_functions() {
-        let ai_fun = "Build me a web server which can receive the ios front facing camera feed.".to_string();
-        let res = ai_task_request(ai_fun, "Managing Agent","Defining user requirements" , convert_user_input_to_goal).await;
-        dbg!(res);
+    #[test]
+    fn test_extend_ai_functions() {
+        let ai_fun = "Build me a web server which can receive the ios front facing camera feed.";
+        let res = extend_ai_functions(convert_user_input_to_goal, ai_fun);
+        assert_eq!(res.role, "system");
+        assert!(res.content.contains(convert_user_input_to_goal(ai_fun)));
+        assert!(res.content.contains(ai_fun));
     }
 }
\ No newline at end of file
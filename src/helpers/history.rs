@@ -0,0 +1,158 @@
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+};
+
+use crate::errors::{BotError, BotResult};
+
+// Tag following the newest backend revision that compiled
+pub const LAST_BUILDING_TAG: &str = "hannah/last-building";
+
+// Generated code is committed under this identity, whatever the user's git config says
+const AUTHOR_NAME: &str = "Hannah";
+const AUTHOR_EMAIL: &str = "hannah@localhost";
+
+// What produced a revision, used as its commit message
+pub struct Revision<'a> {
+    pub agent: &'a str,
+    pub ai_function: &'a str,
+    pub iteration: u8,
+}
+
+impl fmt::Display for Revision<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {} (iteration {})", self.agent, self.ai_function, self.iteration)
+    }
+}
+
+// Every version of the generated code as commits in the workspace's own git repository
+pub struct CodeHistory {
+    root: PathBuf,
+}
+
+impl CodeHistory {
+    // Creates the repository if the workspace does not have one yet. Only a .git directory in the
+    // workspace itself counts, so a workspace inside another checkout gets its own history.
+    pub fn init(root: &Path) -> BotResult<Self> {
+        let history = Self { root: root.to_path_buf() };
+        if !root.join(".git").exists() {
+            history.git(&["init", "--quiet"])?;
+        }
+        Ok(history)
+    }
+    // Every revision is `git add --all` and a rollback restores the whole workspace, so in a
+    // repository that is not only Hannah's the user's uncommitted work would be swept up in both.
+    // Such a repository has to be clean before a run starts.
+    pub fn check_no_user_changes(&self) -> BotResult<()> {
+        let authors = self.git(&["log", "--format=%ae"]).unwrap_or_default();
+        let only_ours = !authors.is_empty() && authors.lines().all(|author| author == AUTHOR_EMAIL);
+        if only_ours || self.git(&["status", "--porcelain"])?.is_empty() {
+            return Ok(());
+        }
+        Err(BotError::Config(format!(
+            "{} is a git repository with uncommitted changes, commit or stash them before running Hannah in it",
+            self.root.display()
+        )))
+    }
+    fn git(&self, args: &[&str]) -> BotResult<String> {
+        let output = Command::new("git")
+            .args(["-c", &format!("user.name={}", AUTHOR_NAME), "-c", &format!("user.email={}", AUTHOR_EMAIL)])
+            .args(args)
+            .current_dir(&self.root)
+            .stdin(Stdio::null())
+            .output()
+            .map_err(|e| BotError::Config(format!("git is needed to keep the code history: {}", e)))?;
+        if !output.status.success() {
+            return Err(BotError::Config(format!(
+                "git {} failed in {}: {}",
+                args.join(" "),
+                self.root.display(),
+                String::from_utf8_lossy(&output.stderr).trim()
+            )));
+        }
+        Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+    }
+    // Commits everything that changed (minus .gitignore'd build output). Returns the new commit,
+    // or None when there was nothing to commit.
+    pub fn commit(&self, message: &str) -> BotResult<Option<String>> {
+        self.git(&["add", "--all"])?;
+        if self.git(&["status", "--porcelain"])?.is_empty() {
+            return Ok(None);
+        }
+        self.git(&["commit", "--quiet", "--no-verify", "-m", message])?;
+        Ok(Some(self.git(&["rev-parse", "HEAD"])?))
+    }
+    pub fn tag(&self, name: &str) -> BotResult<()> {
+        self.git(&["tag", "--force", name, "HEAD"])?;
+        Ok(())
+    }
+    pub fn tagged(&self, name: &str) -> BotResult<Option<String>> {
+        match self.git(&["rev-parse", "--verify", "--quiet", &format!("refs/tags/{}^{{commit}}", name)]) {
+            Ok(rev) => Ok(Some(rev)),
+            Err(_) => Ok(None),
+        }
+    }
+    // Puts `path` (a file or a whole directory) back the way it was at `rev` and commits that as a
+    // new revision. Files added under `path` since `rev` are removed.
+    pub fn restore(&self, path: &Path, rev: &str, message: &str) -> BotResult<Option<String>> {
+        let path = path.strip_prefix(&self.root).unwrap_or(path).to_string_lossy().to_string();
+        let path = if path.is_empty() { ".".to_string() } else { path };
+        let source = format!("--source={}", rev);
+        self.git(&["restore", &source, "--staged", "--worktree", "--", &path])?;
+        self.commit(message)
+    }
+}
+
+// Commits the workspace as it is now, named after what produced it
+pub fn commit_revision(root: &Path, revision: &Revision) -> BotResult<Option<String>> {
+    CodeHistory::init(root)?.commit(&revision.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn test_commits_and_rolls_back_to_tagged_revision() {
+        let root = std::env::temp_dir().join(format!("hannah_history_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join("src")).unwrap();
+        let main_file = root.join("src/main.rs");
+        let history = CodeHistory::init(&root).unwrap();
+
+        fs::write(&main_file, "fn main() {}").unwrap();
+        let revision = Revision { agent: "Backend Developer", ai_function: "print_backend_webserver_code", iteration: 0 };
+        assert!(history.commit(&revision.to_string()).unwrap().is_some());
+        assert!(history.commit("nothing changed").unwrap().is_none());
+        assert!(history.tagged(LAST_BUILDING_TAG).unwrap().is_none());
+        history.tag(LAST_BUILDING_TAG).unwrap();
+        let good = history.tagged(LAST_BUILDING_TAG).unwrap().unwrap();
+
+        fs::write(&main_file, "fn main() { broken").unwrap();
+        fs::write(root.join("src/db.rs"), "pub fn connect() {").unwrap();
+        history.commit("Backend Developer: print_fixed_code (iteration 1)").unwrap();
+        history.restore(&root, &good, "roll back").unwrap();
+        assert_eq!(fs::read_to_string(&main_file).unwrap(), "fn main() {}");
+        assert!(!root.join("src/db.rs").exists());
+
+        let log = history.git(&["log", "--format=%s"]).unwrap();
+        let log: Vec<&str> = log.lines().collect();
+        assert_eq!(log, vec!["roll back", "Backend Developer: print_fixed_code (iteration 1)", "Backend Developer: print_backend_webserver_code (iteration 0)"]);
+
+        // our own work in progress is fine, a user's is not
+        fs::write(root.join("notes.txt"), "todo").unwrap();
+        assert!(history.check_no_user_changes().is_ok());
+        Command::new("git")
+            .args(["-c", "user.name=User", "-c", "user.email=user@example.com", "commit", "--quiet", "--allow-empty", "-m", "user work"])
+            .current_dir(&root)
+            .status()
+            .unwrap();
+        assert!(matches!(history.check_no_user_changes(), Err(BotError::Config(_))));
+        history.commit("notes").unwrap();
+        assert!(history.check_no_user_changes().is_ok());
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,104 @@
+use serde::{Deserialize, Serialize};
+use std::{
+    path::Path,
+    process::{Command, Stdio},
+    sync::Mutex,
+};
+
+use super::diagnostics::{run_cargo_with_diagnostics, Diagnostic};
+use crate::errors::BotResult;
+
+// The "lints" section of the config file. Lints or lint groups in `deny` fail the backend like
+// compiler errors do; everything else clippy reports is only counted.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct LintPolicy {
+    pub deny: Vec<String>,
+    pub rustfmt: bool,
+}
+
+impl Default for LintPolicy {
+    fn default() -> Self {
+        Self {
+            deny: vec!["clippy::correctness".to_string(), "clippy::suspicious".to_string()],
+            rustfmt: true,
+        }
+    }
+}
+
+static POLICY: Mutex<Option<LintPolicy>> = Mutex::new(None);
+
+pub fn set_lint_policy(policy: LintPolicy) {
+    *POLICY.lock().unwrap_or_else(|e| e.into_inner()) = Some(policy);
+}
+
+pub fn lint_policy() -> LintPolicy {
+    POLICY.lock().unwrap_or_else(|e| e.into_inner()).clone().unwrap_or_default()
+}
+
+pub struct LintOutcome {
+    // every warning and error clippy reported
+    pub lint_count: usize,
+    // the ones the policy turns into failures
+    pub denied: Vec<Diagnostic>,
+}
+
+impl LintOutcome {
+    pub fn from_diagnostics(diagnostics: Vec<Diagnostic>) -> Self {
+        let lints: Vec<Diagnostic> = diagnostics.into_iter().filter(|d| d.level == "warning" || d.is_error()).collect();
+        Self { lint_count: lints.len(), denied: lints.into_iter().filter(|d| d.is_error()).collect() }
+    }
+}
+
+pub fn clippy_args(policy: &LintPolicy) -> Vec<String> {
+    let mut args = vec!["clippy".to_string(), "--".to_string()];
+    for lint in &policy.deny {
+        args.push("-D".to_string());
+        args.push(lint.clone());
+    }
+    args
+}
+
+// Formats the workspace in place. Returns false when rustfmt is not installed or gave up, in
+// which case the code is left as it was.
+pub fn run_rustfmt(root: &Path) -> BotResult<bool> {
+    let status = Command::new("cargo")
+        .arg("fmt")
+        .current_dir(root)
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()?;
+    Ok(status.success())
+}
+
+// None when clippy is not installed: the run failed without reporting any error
+pub fn run_clippy(root: &Path, policy: &LintPolicy) -> BotResult<Option<LintOutcome>> {
+    let args = clippy_args(policy);
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    let outcome = run_cargo_with_diagnostics(root, &args)?;
+    if !outcome.success && !outcome.diagnostics.iter().any(Diagnostic::is_error) {
+        return Ok(None);
+    }
+    Ok(Some(LintOutcome::from_diagnostics(outcome.diagnostics)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::helpers::diagnostics::parse_cargo_messages;
+
+    const CLIPPY_STDOUT: &str = r#"{"reason":"compiler-message","package_id":"app 0.1.0","message":{"rendered":"warning: this expression creates a reference which is immediately dereferenced by the compiler","children":[],"code":{"code":"clippy::needless_borrow","explanation":null},"level":"warning","message":"this expression creates a reference which is immediately dereferenced by the compiler","spans":[{"byte_end":30,"byte_start":25,"column_end":20,"column_start":15,"expansion":null,"file_name":"src/main.rs","is_primary":true,"label":null,"line_end":4,"line_start":4,"suggested_replacement":null,"suggestion_applicability":null,"text":[]}]}}
+{"reason":"compiler-message","package_id":"app 0.1.0","message":{"rendered":"error: this comparison involving the minimum or maximum element for this type contains a case that is always true or always false","children":[],"code":{"code":"clippy::absurd_extreme_comparisons","explanation":null},"level":"error","message":"this comparison involving the minimum or maximum element for this type contains a case that is always true or always false","spans":[{"byte_end":60,"byte_start":50,"column_end":18,"column_start":8,"expansion":null,"file_name":"src/main.rs","is_primary":true,"label":null,"line_end":7,"line_start":7,"suggested_replacement":null,"suggestion_applicability":null,"text":[]}]}}
+{"reason":"build-finished","success":false}"#;
+
+    #[test]
+    fn test_denied_lints_are_failures_and_all_are_counted() {
+        let policy = LintPolicy { deny: vec!["clippy::correctness".to_string()], rustfmt: false };
+        assert_eq!(clippy_args(&policy), vec!["clippy", "--", "-D", "clippy::correctness"]);
+
+        let outcome = LintOutcome::from_diagnostics(parse_cargo_messages(CLIPPY_STDOUT));
+        assert_eq!(outcome.lint_count, 2);
+        assert_eq!(outcome.denied.len(), 1);
+        assert_eq!(outcome.denied[0].code.as_deref(), Some("clippy::absurd_extreme_comparisons"));
+    }
+}
@@ -0,0 +1,185 @@
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashSet,
+    fs,
+    path::{Component, Path, PathBuf},
+};
+
+use super::dependencies::DependencyRequest;
+use crate::{
+    errors::{BotError, BotResult},
+    models::general::{
+        schema::{object_schema, JsonSchema},
+        workspace::Workspace,
+    },
+};
+
+// Directories in the workspace that generated files may never touch
+const PROTECTED_DIRS: &[&str] = &[".cargo", ".git", "target"];
+
+// One file of generated code, path relative to the workspace root
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct GeneratedFile {
+    pub path: String,
+    pub contents: String,
+}
+
+impl JsonSchema for GeneratedFile {
+    fn json_schema() -> serde_json::Value {
+        object_schema(&[("path", String::json_schema()), ("contents", String::json_schema())])
+    }
+}
+
+// What the backend generation functions answer with: every file they want written and the
+// crates they need. Files not listed are left as they are.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct FileManifest {
+    pub files: Vec<GeneratedFile>,
+    #[serde(default)]
+    pub dependencies: Vec<DependencyRequest>,
+}
+
+impl JsonSchema for FileManifest {
+    fn json_schema() -> serde_json::Value {
+        object_schema(&[
+            ("files", Vec::<GeneratedFile>::json_schema()),
+            ("dependencies", Vec::<DependencyRequest>::json_schema()),
+        ])
+    }
+}
+
+fn unsafe_path(path: &str, reason: &str) -> BotError {
+    BotError::UnsafePath { path: path.to_string(), reason: reason.to_string() }
+}
+
+// Where `path` lands in the workspace, refusing anything that could end up outside it or in
+// the repository, build output or bot artifacts. Only plain relative components are allowed,
+// so no `..`, no absolute paths and no drive prefixes.
+pub fn resolve_in_workspace(workspace: &Workspace, path: &str) -> BotResult<PathBuf> {
+    let relative = Path::new(path.trim());
+    if relative.as_os_str().is_empty() {
+        return Err(unsafe_path(path, "empty path"));
+    }
+    if !relative.components().all(|c| matches!(c, Component::Normal(_) | Component::CurDir)) {
+        return Err(unsafe_path(path, "must be relative to the workspace and must not contain .."));
+    }
+    let relative: PathBuf = relative.components().filter(|c| matches!(c, Component::Normal(_))).collect();
+    let first = relative.components().next().map(|c| c.as_os_str().to_string_lossy().to_string()).unwrap_or_default();
+    if PROTECTED_DIRS.contains(&first.as_str()) {
+        return Err(unsafe_path(path, "inside a protected directory"));
+    }
+    if relative == Path::new("Cargo.toml") {
+        return Err(unsafe_path(path, "crates are added through dependencies, not by writing Cargo.toml"));
+    }
+    let resolved = workspace.root().join(&relative);
+    if resolved.starts_with(&workspace.artifact_dir) || resolved == workspace.template_path {
+        return Err(unsafe_path(path, "reserved for the bot"));
+    }
+    Ok(resolved)
+}
+
+impl FileManifest {
+    // Checks every path before anything is written, so a bad manifest changes nothing
+    pub fn write(&self, workspace: &Workspace) -> BotResult<Vec<PathBuf>> {
+        if self.files.is_empty() {
+            return Err(unsafe_path("", "the manifest lists no files"));
+        }
+        let mut seen = HashSet::new();
+        let mut targets = vec![];
+        for file in &self.files {
+            let target = resolve_in_workspace(workspace, &file.path)?;
+            if !seen.insert(target.clone()) {
+                return Err(unsafe_path(&file.path, "listed more than once"));
+            }
+            targets.push(target);
+        }
+        for (file, target) in self.files.iter().zip(&targets) {
+            if let Some(parent) = target.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            fs::write(target, &file.contents)?;
+        }
+        Ok(targets)
+    }
+    // Cargo.toml and every .rs file under src/, the way the prompts show a project to the model
+    pub fn read_project(workspace: &Workspace) -> BotResult<Self> {
+        let root = workspace.root();
+        let mut paths = vec![root.join("Cargo.toml")];
+        collect_rust_files(&root.join("src"), &mut paths)?;
+        let mut files = vec![];
+        for path in paths {
+            if path == workspace.template_path || !path.is_file() {
+                continue;
+            }
+            let relative = path.strip_prefix(root).unwrap_or(&path).to_string_lossy().replace('\\', "/");
+            files.push(GeneratedFile { path: relative, contents: fs::read_to_string(&path)? });
+        }
+        Ok(Self { files, dependencies: vec![] })
+    }
+    pub fn render(&self) -> String {
+        self.files
+            .iter()
+            .map(|f| format!("FILE: {}\n```\n{}\n```", f.path, f.contents.trim_end()))
+            .collect::<Vec<String>>()
+            .join("\n\n")
+    }
+}
+
+fn collect_rust_files(dir: &Path, paths: &mut Vec<PathBuf>) -> BotResult<()> {
+    if !dir.is_dir() {
+        return Ok(());
+    }
+    let mut entries: Vec<PathBuf> = fs::read_dir(dir)?.filter_map(|e| e.ok().map(|e| e.path())).collect();
+    entries.sort();
+    for path in entries {
+        if path.is_dir() {
+            collect_rust_files(&path, paths)?;
+        } else if path.extension().map(|e| e == "rs").unwrap_or(false) {
+            paths.push(path);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::general::workspace::WorkspaceConfig;
+
+    #[test]
+    fn test_paths_must_stay_inside_the_workspace() {
+        let workspace = Workspace::from_config(&WorkspaceConfig::default(), Some(PathBuf::from("/srv/todo_app")));
+        assert_eq!(resolve_in_workspace(&workspace, "src/routes/todos.rs").unwrap(), PathBuf::from("/srv/todo_app/src/routes/todos.rs"));
+        assert_eq!(resolve_in_workspace(&workspace, "./src/db.rs").unwrap(), PathBuf::from("/srv/todo_app/src/db.rs"));
+        for bad in ["Cargo.toml", "../escape.rs", "src/../../escape.rs", "/etc/passwd", ".git/config", "target/debug/app", "artifacts/report.json", "src/gpt_created.rs", ""] {
+            assert!(matches!(resolve_in_workspace(&workspace, bad), Err(BotError::UnsafePath { .. })), "{} accepted", bad);
+        }
+    }
+
+    #[test]
+    fn test_manifest_written_and_read_back() {
+        let root = std::env::temp_dir().join(format!("hannah_manifest_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        let workspace = Workspace::from_config(&WorkspaceConfig::default(), Some(root.clone()));
+        let manifest = FileManifest {
+            files: vec![
+                GeneratedFile { path: "src/main.rs".to_string(), contents: "mod routes;\nfn main() {}\n".to_string() },
+                GeneratedFile { path: "src/routes/mod.rs".to_string(), contents: "pub fn list() {}\n".to_string() },
+            ],
+            dependencies: vec![],
+        };
+        assert_eq!(manifest.write(&workspace).unwrap().len(), 2);
+        let project = FileManifest::read_project(&workspace).unwrap();
+        let paths: Vec<&str> = project.files.iter().map(|f| f.path.as_str()).collect();
+        assert_eq!(paths, vec!["src/main.rs", "src/routes/mod.rs"]);
+        assert!(project.render().contains("FILE: src/routes/mod.rs\n```\npub fn list() {}\n```"));
+
+        let escaping = FileManifest {
+            files: vec![GeneratedFile { path: "../outside.rs".to_string(), contents: String::new() }],
+            dependencies: vec![],
+        };
+        assert!(escaping.write(&workspace).is_err());
+        assert!(!root.join("../outside.rs").exists());
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -1,2 +1,14 @@
+pub mod cargo_test;
+pub mod cli;
 pub mod command_line;
-pub mod general;
\ No newline at end of file
+pub mod contract_test;
+pub mod dependencies;
+pub mod diagnostics;
+pub mod extract;
+pub mod general;
+pub mod history;
+pub mod lint;
+pub mod manifest;
+pub mod patch;
+pub mod scaffold;
+pub mod server;
\ No newline at end of file
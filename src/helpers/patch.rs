@@ -0,0 +1,172 @@
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, fs, path::PathBuf};
+
+use crate::{
+    errors::{BotError, BotResult},
+    models::general::{
+        schema::{object_schema, JsonSchema},
+        workspace::Workspace,
+    },
+};
+use super::{
+    dependencies::DependencyRequest,
+    manifest::{resolve_in_workspace, FileManifest, GeneratedFile},
+};
+
+// Replace the one place in `path` that reads `search` with `replace`. An empty search creates
+// the file.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct FileEdit {
+    pub path: String,
+    pub search: String,
+    pub replace: String,
+}
+
+impl JsonSchema for FileEdit {
+    fn json_schema() -> serde_json::Value {
+        object_schema(&[
+            ("path", String::json_schema()),
+            ("search", String::json_schema()),
+            ("replace", String::json_schema()),
+        ])
+    }
+}
+
+// What the improve and fix functions answer with: targeted edits, whole files for the ones
+// rewritten from scratch and crates to add
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct EditPlan {
+    #[serde(default)]
+    pub edits: Vec<FileEdit>,
+    #[serde(default)]
+    pub files: Vec<GeneratedFile>,
+    #[serde(default)]
+    pub dependencies: Vec<DependencyRequest>,
+}
+
+impl JsonSchema for EditPlan {
+    fn json_schema() -> serde_json::Value {
+        object_schema(&[
+            ("edits", Vec::<FileEdit>::json_schema()),
+            ("files", Vec::<GeneratedFile>::json_schema()),
+            ("dependencies", Vec::<DependencyRequest>::json_schema()),
+        ])
+    }
+}
+
+impl EditPlan {
+    // Applies every edit in memory first; if any of them conflicts nothing is written and all
+    // conflicts are reported together, so the model can fix them in one go
+    pub fn apply(&self, workspace: &Workspace) -> BotResult<Vec<PathBuf>> {
+        if self.edits.is_empty() && self.files.is_empty() {
+            if !self.dependencies.is_empty() {
+                return Ok(vec![]);
+            }
+            return Err(BotError::PatchConflict { conflicts: vec!["the plan contains no edits, files or dependencies".to_string()] });
+        }
+        // keyed by where the file lands, so "src/main.rs" and "./src/main.rs" are the same file
+        let mut contents: BTreeMap<PathBuf, String> = BTreeMap::new();
+        let mut conflicts = vec![];
+        let edit_targets = self.edits.iter().map(|e| resolve_in_workspace(workspace, &e.path)).collect::<BotResult<Vec<PathBuf>>>()?;
+        for file in &self.files {
+            let target = resolve_in_workspace(workspace, &file.path)?;
+            if edit_targets.contains(&target) {
+                conflicts.push(format!("{}: listed in both edits and files", file.path));
+            }
+            if contents.insert(target, file.contents.clone()).is_some() {
+                conflicts.push(format!("{}: listed more than once in files", file.path));
+            }
+        }
+        for (i, (edit, target)) in self.edits.iter().zip(edit_targets).enumerate() {
+            let current = match contents.get(&target) {
+                Some(current) => Some(current.clone()),
+                None if target.is_file() => Some(fs::read_to_string(&target)?),
+                None => None,
+            };
+            let conflict = |message: &str| format!("edit {} on {}: {}", i + 1, edit.path, message);
+            match (current, edit.search.is_empty()) {
+                (None, true) => {
+                    contents.insert(target, edit.replace.clone());
+                }
+                (Some(_), true) => conflicts.push(conflict("search is empty but the file exists, quote the lines to replace")),
+                (None, false) => conflicts.push(conflict("no such file")),
+                (Some(current), false) => match current.matches(edit.search.as_str()).count() {
+                    1 => {
+                        contents.insert(target, current.replacen(edit.search.as_str(), &edit.replace, 1));
+                    }
+                    0 => conflicts.push(conflict("search text not found, copy it exactly from the current file")),
+                    n => conflicts.push(conflict(&format!("search text found {} times, include more surrounding lines", n))),
+                },
+            }
+        }
+        if !conflicts.is_empty() {
+            return Err(BotError::PatchConflict { conflicts });
+        }
+        let files = contents
+            .into_iter()
+            .map(|(target, contents)| {
+                let path = target.strip_prefix(workspace.root()).unwrap_or(&target).to_string_lossy().to_string();
+                GeneratedFile { path, contents }
+            })
+            .collect();
+        FileManifest { files, dependencies: vec![] }.write(workspace)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::general::workspace::WorkspaceConfig;
+
+    fn edit(path: &str, search: &str, replace: &str) -> FileEdit {
+        FileEdit { path: path.to_string(), search: search.to_string(), replace: replace.to_string() }
+    }
+
+    #[test]
+    fn test_edits_apply_or_report_every_conflict() {
+        let root = std::env::temp_dir().join(format!("hannah_patch_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join("src")).unwrap();
+        let main_file = root.join("src/main.rs");
+        let original = "fn a() {}\nfn b() {}\nfn b() {}\nfn main() {}\n";
+        fs::write(&main_file, original).unwrap();
+        let workspace = Workspace::from_config(&WorkspaceConfig::default(), Some(root.clone()));
+
+        let plan = EditPlan {
+            edits: vec![edit("src/main.rs", "fn a() {}", "fn a() { todo!() }"), edit("src/main.rs", "fn b() {}", ""), edit("src/db.rs", "x", "y")],
+            files: vec![],
+            dependencies: vec![],
+        };
+        match plan.apply(&workspace) {
+            Err(BotError::PatchConflict { conflicts }) => {
+                assert_eq!(conflicts.len(), 2);
+                assert!(conflicts[0].contains("found 2 times"));
+                assert!(conflicts[1].contains("no such file"));
+            }
+            other => panic!("expected conflicts, got {:?}", other),
+        }
+        assert_eq!(fs::read_to_string(&main_file).unwrap(), original);
+
+        let plan = EditPlan {
+            edits: vec![
+                edit("src/main.rs", "fn a() {}", "fn a() { todo!() }"),
+                edit("src/main.rs", "fn main() {}", "mod db;\nfn main() {}"),
+                edit("src/db.rs", "", "pub fn connect() {}\n"),
+            ],
+            files: vec![],
+            dependencies: vec![],
+        };
+        plan.apply(&workspace).unwrap();
+        assert_eq!(fs::read_to_string(&main_file).unwrap(), "fn a() { todo!() }\nfn b() {}\nfn b() {}\nmod db;\nfn main() {}\n");
+        assert_eq!(fs::read_to_string(root.join("src/db.rs")).unwrap(), "pub fn connect() {}\n");
+
+        // a plan may leave out "files", and spellings of the same path edit the same file
+        let plan: EditPlan = serde_json::from_str(
+            r#"{"edits": [{"path": "src/db.rs", "search": "connect", "replace": "open"}, {"path": "./src/db.rs", "search": "open()", "replace": "open(url: &str)"}]}"#,
+        )
+        .unwrap();
+        plan.apply(&workspace).unwrap();
+        assert_eq!(fs::read_to_string(root.join("src/db.rs")).unwrap(), "pub fn open(url: &str) {}\n");
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
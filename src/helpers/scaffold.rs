@@ -0,0 +1,98 @@
+use std::{fs, path::Path};
+
+use crate::{errors::BotResult, models::general::workspace::Workspace};
+use super::history::CodeHistory;
+
+// Built-in starting point for the backend agent, so a run does not need an existing project
+const CARGO_TOML_TEMPLATE: &str = include_str!("../../templates/backend/Cargo.toml.template");
+pub const MAIN_RS_TEMPLATE: &str = include_str!("../../templates/backend/main.rs.template");
+const GITIGNORE_TEMPLATE: &str = include_str!("../../templates/backend/gitignore.template");
+
+// Cargo package names: lowercase alphanumerics, `_` and `-`, not starting with a digit
+pub fn package_name(root: &Path) -> String {
+    let dir_name = root
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let name: String = dir_name
+        .to_lowercase()
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '-' { c } else { '_' })
+        .collect();
+    match name.chars().next() {
+        Some(c) if c.is_ascii_alphabetic() => name,
+        Some(_) => format!("app_{}", name),
+        None => "generated_backend".to_string(),
+    }
+}
+
+fn write_if_missing(path: &Path, contents: &str) -> BotResult<bool> {
+    if path.exists() {
+        return Ok(false);
+    }
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(path, contents)?;
+    Ok(true)
+}
+
+// Creates a fresh cargo project in the workspace, under git. Files that already exist are left
+// alone, so pointing at an existing project keeps working as long as its repository is clean.
+// Returns true if anything was written.
+pub fn scaffold_workspace(workspace: &Workspace) -> BotResult<bool> {
+    let root = workspace.root();
+    if root.join(".git").exists() {
+        CodeHistory::init(root)?.check_no_user_changes()?;
+    }
+    let cargo_toml = CARGO_TOML_TEMPLATE.replace("{{package_name}}", &package_name(root));
+    let mut created = false;
+    created |= write_if_missing(&root.join("Cargo.toml"), &cargo_toml)?;
+    created |= write_if_missing(&root.join(".gitignore"), GITIGNORE_TEMPLATE)?;
+    created |= write_if_missing(&workspace.main_file, MAIN_RS_TEMPLATE)?;
+    fs::create_dir_all(&workspace.artifact_dir)?;
+    // on a resume or an existing project, whatever changed since the last commit is not scaffolding
+    if !created {
+        return Ok(false);
+    }
+    // the first revision of the code history
+    CodeHistory::init(root)?.commit("Scaffold workspace")?;
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::general::workspace::WorkspaceConfig;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_package_name_is_cargo_safe() {
+        assert_eq!(package_name(Path::new("/srv/Todo App")), "todo_app");
+        assert_eq!(package_name(Path::new("/srv/2024-shop")), "app_2024-shop");
+        assert_eq!(package_name(Path::new("/")), "generated_backend");
+    }
+
+    #[test]
+    fn test_scaffold_keeps_existing_files() {
+        let root = std::env::temp_dir().join(format!("hannah_scaffold_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        let workspace = Workspace::from_config(&WorkspaceConfig::default(), Some(PathBuf::from(&root)));
+
+        assert!(scaffold_workspace(&workspace).unwrap());
+        let cargo_toml = fs::read_to_string(root.join("Cargo.toml")).unwrap();
+        assert!(cargo_toml.contains("actix-web"));
+        assert!(!cargo_toml.contains("{{package_name}}"));
+        assert!(workspace.main_file.exists());
+        // the template is only shown to the model, it is not part of the project
+        assert!(!workspace.template_path.exists());
+
+        fs::write(&workspace.main_file, "fn main() {}").unwrap();
+        assert!(!scaffold_workspace(&workspace).unwrap());
+        assert_eq!(fs::read_to_string(&workspace.main_file).unwrap(), "fn main() {}");
+        let commits = std::process::Command::new("git").args(["rev-list", "--count", "HEAD"]).current_dir(&root).output().unwrap();
+        assert_eq!(String::from_utf8_lossy(&commits.stdout).trim(), "1");
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
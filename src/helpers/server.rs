@@ -0,0 +1,173 @@
+use std::{
+    fs::{self, File},
+    net::TcpListener,
+    path::Path,
+    process::{Child, Command, Stdio},
+    time::{Duration, Instant},
+};
+
+use crate::errors::{BotError, BotResult};
+
+// The generated server reads its port from this variable, defaulting to 1337
+pub const PORT_ENV: &str = "PORT";
+
+// Asks the OS for a port nobody is listening on. There is a small window where another process
+// could take it before the server binds, which the readiness probe would then report.
+pub fn pick_free_port() -> BotResult<u16> {
+    let listener = TcpListener::bind("127.0.0.1:0")?;
+    Ok(listener.local_addr()?.port())
+}
+
+// A `cargo run` of the generated backend. The child gets its own process group so stopping it
+// also stops the server binary cargo started, and dropping the handle always stops it.
+#[derive(Debug)]
+pub struct ServerUnderTest {
+    child: Option<Child>,
+    port: u16,
+    log_path: std::path::PathBuf,
+}
+
+impl ServerUnderTest {
+    pub fn start(root: &Path, log_path: &Path) -> BotResult<Self> {
+        let mut command = Command::new("cargo");
+        command.arg("run").current_dir(root);
+        Self::spawn(command, log_path)
+    }
+    fn spawn(mut command: Command, log_path: &Path) -> BotResult<Self> {
+        let port = pick_free_port()?;
+        if let Some(parent) = log_path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let log = File::create(log_path)?;
+        command
+            .env(PORT_ENV, port.to_string())
+            .stdin(Stdio::null())
+            .stdout(log.try_clone()?)
+            .stderr(log);
+        #[cfg(unix)]
+        {
+            use std::os::unix::process::CommandExt;
+            command.process_group(0);
+        }
+        let child = command.spawn()?;
+        Ok(Self { child: Some(child), port, log_path: log_path.to_path_buf() })
+    }
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+    pub fn base_url(&self) -> String {
+        format!("http://127.0.0.1:{}", self.port)
+    }
+    // Last lines of the captured stdout/stderr, for error messages
+    pub fn log_tail(&self, lines: usize) -> String {
+        let log = fs::read_to_string(&self.log_path).unwrap_or_default();
+        let all: Vec<&str> = log.lines().collect();
+        all[all.len().saturating_sub(lines)..].join("\n")
+    }
+    // Polls until the port accepts connections. Fails early if the process exits.
+    pub async fn wait_until_ready(&mut self, timeout: Duration) -> BotResult<()> {
+        let started = Instant::now();
+        loop {
+            let connect = tokio::net::TcpStream::connect(("127.0.0.1", self.port));
+            if let Ok(Ok(_)) = tokio::time::timeout(Duration::from_secs(1), connect).await {
+                return Ok(());
+            }
+            if let Some(child) = self.child.as_mut() {
+                if let Some(status) = child.try_wait()? {
+                    return Err(BotError::EndpointTest {
+                        route: self.base_url(),
+                        message: format!("server exited with {} before accepting connections:\n{}", status, self.log_tail(30)),
+                    });
+                }
+            }
+            if started.elapsed() > timeout {
+                return Err(BotError::EndpointTest {
+                    route: self.base_url(),
+                    message: format!("server did not accept connections within {}s:\n{}", timeout.as_secs(), self.log_tail(30)),
+                });
+            }
+            tokio::time::sleep(Duration::from_millis(250)).await;
+        }
+    }
+    // SIGTERM to the whole group, then SIGKILL if it is still around after a grace period
+    pub async fn stop(&mut self) {
+        let mut child = match self.child.take() {
+            Some(child) => child,
+            None => return,
+        };
+        signal_group(&child, "-TERM");
+        let deadline = Instant::now() + Duration::from_secs(3);
+        while Instant::now() < deadline {
+            if let Ok(Some(_)) = child.try_wait() {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+        kill(child);
+    }
+}
+
+#[cfg(unix)]
+fn signal_group(child: &Child, signal: &str) {
+    let group = format!("-{}", child.id());
+    let _ = Command::new("kill").args([signal, "--", &group]).stderr(Stdio::null()).status();
+}
+
+#[cfg(not(unix))]
+fn signal_group(_child: &Child, _signal: &str) {}
+
+// SIGKILL right away; reaping happens off the current thread so this never blocks
+fn kill(mut child: Child) {
+    signal_group(&child, "-KILL");
+    let _ = child.kill();
+    if !matches!(child.try_wait(), Ok(Some(_))) {
+        std::thread::spawn(move || child.wait());
+    }
+}
+
+// Dropping cannot wait for a graceful shutdown, e.g. when Ctrl-C cancels a run
+impl Drop for ServerUnderTest {
+    fn drop(&mut self) {
+        if let Some(child) = self.child.take() {
+            kill(child);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pick_free_port_is_bindable() {
+        let port = pick_free_port().unwrap();
+        assert!(port > 0);
+        assert!(TcpListener::bind(("127.0.0.1", port)).is_ok());
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_early_exit_is_reported_with_log() {
+        let log_path = std::env::temp_dir().join(format!("hannah_server_{}.log", std::process::id()));
+        let mut command = Command::new("sh");
+        command.args(["-c", "echo \"cannot bind port $PORT\"; exit 3"]);
+        let mut server = ServerUnderTest::spawn(command, &log_path).unwrap();
+        let err = server.wait_until_ready(Duration::from_secs(10)).await.unwrap_err();
+        assert!(err.to_string().contains(&format!("cannot bind port {}", server.port())));
+        fs::remove_file(&log_path).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_stop_kills_process_group() {
+        let log_path = std::env::temp_dir().join(format!("hannah_server_stop_{}.log", std::process::id()));
+        let mut command = Command::new("sh");
+        command.args(["-c", "sleep 30 & wait"]);
+        let mut server = ServerUnderTest::spawn(command, &log_path).unwrap();
+        let started = Instant::now();
+        server.stop().await;
+        assert!(started.elapsed() < Duration::from_secs(3));
+        assert!(server.child.is_none());
+        fs::remove_file(&log_path).unwrap();
+    }
+}
@@ -9,19 +9,126 @@ macro_rules! get_function_string {
 #[macro_use]
 mod ai_functions;
 mod apis;
+mod errors;
 mod helpers;
 mod models;
-use helpers::command_line::get_user_response;
+use apis::{cassette::Cassette, client::init_client_context};
+use errors::{BotError, BotResult};
+use helpers::{
+    cli::{parse_args, print_checkpoint, print_run_result, print_session_list, CliCommand, USAGE},
+    command_line::{get_user_response, set_progress_to_stderr, PrintCommand},
+    dependencies::set_dependency_policy,
+    lint::set_lint_policy,
+};
+use models::{agents_manager::{session::Session, ManagingAgent}, general::{config::AppConfig, usage::set_pricing, workspace::Workspace}};
+use std::env;
+
+fn exit_with_error(e: BotError) -> ! {
+    PrintCommand::Issue.print_agent_message("Project Manager", &format!("[{}] {}", e.kind(), e));
+    std::process::exit(1);
+}
+
+// LLM calls of a session go to its own cassette unless LLM_CASSETTE already says otherwise
+fn record_into(session: &Session) {
+    if env::var("LLM_CASSETTE").is_err() {
+        env::set_var("LLM_CASSETTE", session.cassette_path());
+        env::set_var("LLM_CASSETTE_MODE", "record");
+    }
+}
 
 #[tokio::main]
 async fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let cli = parse_args(&args).unwrap_or_else(|e| exit_with_error(e));
+    set_progress_to_stderr(cli.json);
+    // flags win over the environment, which wins over the defaults
+    if let Some(provider) = &cli.provider {
+        env::set_var("LLM_PROVIDER", provider);
+    }
+    if let Some(model) = &cli.model {
+        env::set_var("LLM_MODEL", model);
+    }
+    if !cli.stream {
+        env::set_var("LLM_STREAM", "0");
+    }
+    let mut config = AppConfig::load_or_default(cli.config.as_deref()).unwrap_or_else(|e| exit_with_error(e));
+    if cli.budget_usd.is_some() {
+        config.pricing.budget_usd = cli.budget_usd;
+    }
+    set_pricing(config.pricing.clone());
+    set_dependency_policy(config.dependencies.clone());
+    set_lint_policy(config.lints.clone());
+    let sessions_dir = config.sessions_dir();
 
-
-// need to add a frontend agent
-// need to refactor the code a bit which gives out the file path or name of the written code
-    let usr_req = get_user_response("What software are we building today?");
-    let mut managing_agent: models::agents_manager::ManagingAgent = models::agents_manager::ManagingAgent::new(usr_req).await.expect("Failed to create managing agent");
-    managing_agent.execute_project().await;
-    dbg!(managing_agent);
-    
-}   
+    let managing_agent_res: BotResult<ManagingAgent> = match cli.command {
+        CliCommand::Help => {
+            println!("{}", USAGE);
+            return;
+        }
+        CliCommand::List => {
+            Session::list(&sessions_dir)
+                .and_then(|sessions| print_session_list(&sessions, cli.json))
+                .unwrap_or_else(|e| exit_with_error(e));
+            return;
+        }
+        CliCommand::Show(session_id) => {
+            let checkpoint = Session::open(&sessions_dir, &session_id)
+                .and_then(|session| session.load())
+                .unwrap_or_else(|e| exit_with_error(e));
+            print_checkpoint(&checkpoint, cli.json);
+            return;
+        }
+        // continues an interrupted run from its last checkpoint
+        CliCommand::Resume(session_id) => Session::open(&sessions_dir, &session_id).and_then(|session| {
+            record_into(&session);
+            init_client_context(&config.http, config.retry.clone(), config.models.clone())?;
+            ManagingAgent::resume(session, cli.approve)
+        }),
+        CliCommand::New(request_source) => {
+            let workspace = Workspace::from_config(&config.workspace, cli.workspace.clone());
+            let session = Session::create(&sessions_dir).unwrap_or_else(|e| exit_with_error(e));
+            record_into(&session);
+            init_client_context(&config.http, config.retry.clone(), config.models.clone()).unwrap_or_else(|e| exit_with_error(e));
+            let usr_req = match request_source.read().unwrap_or_else(|e| exit_with_error(e)) {
+                Some(usr_req) => usr_req,
+                None => get_user_response("What software are we building today?").unwrap_or_else(|e| exit_with_error(e)),
+            };
+            ManagingAgent::new(usr_req, workspace, session, cli.approve).await
+        }
+        // runs the request of an old session again, answering every LLM call from its cassette
+        CliCommand::Replay(session_id) => {
+            let recorded = Session::open(&sessions_dir, &session_id).unwrap_or_else(|e| exit_with_error(e));
+            let checkpoint = recorded.load().unwrap_or_else(|e| exit_with_error(e));
+            let cassette = Cassette::load(&recorded.cassette_path()).unwrap_or_else(|e| exit_with_error(e));
+            // request keys include the model, so replay with the provider and model that were recorded;
+            // older cassettes only know the model of their first call
+            if let (None, Some(provider)) = (&cli.provider, &cassette.provider) {
+                env::set_var("LLM_PROVIDER", provider);
+            }
+            if let (None, Some(model)) = (&cli.model, cassette.model.as_ref().or(cassette.interactions.first().map(|i| &i.model))) {
+                env::set_var("LLM_MODEL", model);
+            }
+            env::set_var("LLM_CASSETTE", recorded.cassette_path());
+            env::set_var("LLM_CASSETTE_MODE", "replay");
+            init_client_context(&config.http, config.retry.clone(), config.models.clone()).unwrap_or_else(|e| exit_with_error(e));
+            let session = Session::create(&sessions_dir).unwrap_or_else(|e| exit_with_error(e));
+            // never into the recorded project itself, that would overwrite it
+            let workspace = match cli.workspace.clone() {
+                Some(root) => Workspace::from_config(&config.workspace, Some(root)),
+                None => session.replay_workspace(&checkpoint.workspace),
+            };
+            ManagingAgent::new(checkpoint.user_request, workspace, session, cli.approve).await
+        }
+    };
+    let mut managing_agent = managing_agent_res.unwrap_or_else(|e| exit_with_error(e));
+    PrintCommand::AICall.print_agent_message("Project Manager", &format!("Session {} (continue with `resume {}`)", managing_agent.session_id(), managing_agent.session_id()));
+    let run_res = managing_agent.execute_project().await;
+    if cli.json {
+        print_run_result(managing_agent.session_id(), &run_res, managing_agent.factsheet());
+    } else {
+        dbg!(&managing_agent);
+    }
+    if run_res.is_err() {
+        std::process::exit(1);
+    }
+}
\ No newline at end of file
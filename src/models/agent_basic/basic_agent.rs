@@ -1,8 +1,10 @@
+use serde::{Deserialize, Serialize};
+
 use crate::models::general::llm::Message;
 
-use super::basic_traits::BasicTraits;
+use super::{basic_traits::BasicTraits, memory::{history_budget, select_history, without_file_contents}};
 
-#[derive(Debug, PartialEq)]
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
 pub enum AgentState {
     Discovery,
     Working,
@@ -18,6 +20,19 @@ pub struct BasicAgent {
     pub memory: Vec<Message> 
 }
 
+impl BasicAgent {
+    // Prompts are stored as user turns so the history alternates user/assistant for every provider.
+    // Project files in them are not, every prompt brings its own current copy.
+    pub fn remember(&mut self, prompt: &Message, reply: &str) {
+        self.memory.push(Message { role: "user".to_string(), content: without_file_contents(&prompt.content) });
+        self.memory.push(Message { role: "assistant".to_string(), content: reply.to_string() });
+    }
+    // The part of memory that fits next to `prompt` in the model's context window, up to `max_tokens`
+    pub fn history_for(&self, model: &str, prompt: &Message, max_tokens: Option<usize>) -> Vec<Message> {
+        select_history(&self.memory, history_budget(model, prompt, max_tokens))
+    }
+}
+
 impl BasicTraits for BasicAgent {
     fn new(objective: String, position: String) -> Self {
         Self {
@@ -0,0 +1,199 @@
+use crate::models::general::llm::Message;
+
+// Context windows in tokens, matched by longest prefix like the price table
+const CONTEXT_WINDOWS: &[(&str, usize)] = &[
+    ("gpt-4", 8_192),
+    ("gpt-4-32k", 32_768),
+    ("gpt-4-turbo", 128_000),
+    ("gpt-4o", 128_000),
+    ("gpt-3.5-turbo", 16_385),
+    ("claude", 200_000),
+];
+// Local models are often served with small windows
+const DEFAULT_CONTEXT_WINDOW: usize = 8_192;
+// Per message overhead of the chat format
+const MESSAGE_OVERHEAD_TOKENS: usize = 4;
+// Longest excerpt of a dropped message kept in the summary
+const SUMMARY_EXCERPT_CHARS: usize = 160;
+// Every prompt is sent with this much earlier conversation at most, unless the model settings
+// say otherwise. More makes each call of a long fix loop dearer than the one before.
+pub const DEFAULT_HISTORY_TOKENS: usize = 8_000;
+
+pub fn context_window(model: &str) -> usize {
+    CONTEXT_WINDOWS
+        .iter()
+        .filter(|(name, _)| model.starts_with(name))
+        .max_by_key(|(name, _)| name.len())
+        .map(|(_, tokens)| *tokens)
+        .unwrap_or(DEFAULT_CONTEXT_WINDOW)
+}
+
+// Roughly four characters per token for English and code. Good enough to stay under the limit
+// without shipping a tokenizer per provider.
+pub fn estimate_tokens(messages: &[Message]) -> usize {
+    messages.iter().map(|m| m.content.chars().count() / 4 + MESSAGE_OVERHEAD_TOKENS).sum()
+}
+
+// Tokens left for history once the new prompt and a quarter of the window for the reply are set
+// aside, and never more than `max_tokens`
+pub fn history_budget(model: &str, prompt: &Message, max_tokens: Option<usize>) -> usize {
+    let window = context_window(model);
+    let left = window.saturating_sub(estimate_tokens(std::slice::from_ref(prompt)) + window / 4);
+    left.min(max_tokens.unwrap_or(DEFAULT_HISTORY_TOKENS))
+}
+
+// What of a prompt is worth remembering. FILE: blocks show the project as it was then, and the
+// next prompt shows it as it is now, so only their paths are kept.
+pub fn without_file_contents(prompt: &str) -> String {
+    let mut kept = vec![];
+    let mut lines = prompt.lines().peekable();
+    while let Some(line) = lines.next() {
+        // the first block shares its line with the prompt's label, e.g. "BACKEND_CODE: FILE: Cargo.toml"
+        let is_block = line.contains("FILE: ") && lines.peek().map(|next| next.trim() == "```").unwrap_or(false);
+        if !is_block {
+            kept.push(line.to_string());
+            continue;
+        }
+        kept.push(format!("{} (contents left out)", line));
+        lines.next();
+        for line in lines.by_ref() {
+            if line.trim() == "```" {
+                break;
+            }
+        }
+    }
+    kept.join("\n")
+}
+
+// Agent prompts all start with the signature of their AI function, so the first line says
+// nothing. Name the function and quote the errors it was asked to fix instead; ERROR_BUGS is
+// formatted with {:?}, so it sits on one line.
+fn prompt_excerpt(prompt: &str) -> String {
+    let ai_function = prompt
+        .split_whitespace()
+        .skip_while(|word| *word != "fn")
+        .nth(1)
+        .and_then(|word| word.split(|c: char| !(c.is_alphanumeric() || c == '_')).next());
+    let errors = prompt
+        .split_once("ERROR_BUGS:")
+        .and_then(|(_, rest)| rest.lines().next())
+        .map(|errors| errors.trim().trim_start_matches("Some(").trim_end_matches(')'));
+    match (ai_function, errors) {
+        (Some(ai_function), Some(errors)) => format!("{} on {}", ai_function, errors),
+        (Some(ai_function), None) => ai_function.to_string(),
+        _ => prompt.trim().lines().next().unwrap_or_default().to_string(),
+    }
+}
+
+fn excerpt(message: &Message) -> String {
+    let text = match message.role.as_str() {
+        "assistant" => message.content.clone(),
+        _ => prompt_excerpt(&message.content),
+    };
+    let text = text.split_whitespace().collect::<Vec<&str>>().join(" ");
+    let mut short: String = text.chars().take(SUMMARY_EXCERPT_CHARS).collect();
+    if short.len() < text.len() {
+        short.push_str(" ...");
+    }
+    format!("- {}: {}", message.role, short)
+}
+
+// The newest turns that fit in `budget` tokens. Turns are prompt/reply pairs and are kept or
+// dropped whole. Dropped turns are replaced by a short system note, newest first, naming each
+// function called with the errors it was given and quoting its reply, so the model knows what it
+// already tried.
+pub fn select_history(memory: &[Message], budget: usize) -> Vec<Message> {
+    let turns: Vec<&[Message]> = memory.chunks(2).collect();
+    // leave room for the summary when anything has to go
+    let summary_budget = if estimate_tokens(memory) > budget { (budget / 8).min(512) } else { 0 };
+    let mut used = 0;
+    let mut kept = 0;
+    for turn in turns.iter().rev() {
+        let cost = estimate_tokens(turn);
+        if used + cost + summary_budget > budget {
+            break;
+        }
+        used += cost;
+        kept += 1;
+    }
+    let first_kept = turns.len() - kept;
+    let mut history = vec![];
+    if first_kept > 0 {
+        let mut summary = String::from("EARLIER CONVERSATION (truncated to fit the context window):");
+        for message in turns[..first_kept].iter().rev().flat_map(|turn| turn.iter()) {
+            let line = excerpt(message);
+            if estimate_tokens(&[Message { role: "system".to_string(), content: format!("{}\n{}", summary, line) }]) > summary_budget {
+                break;
+            }
+            summary.push('\n');
+            summary.push_str(&line);
+        }
+        history.push(Message { role: "system".to_string(), content: summary });
+    }
+    history.extend(turns[first_kept..].iter().flat_map(|turn| turn.iter().cloned()));
+    history
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::{ai_functions::aifunc_backend::print_fixed_code, helpers::general::extend_ai_functions};
+
+    // A fix prompt and its reply as the backend developer remembers them
+    fn turn(attempt: usize) -> Vec<Message> {
+        let msg_context = format!(
+            "BROKEN_CODE: {} \n ERROR_BUGS: {:?} \n",
+            "x".repeat(400),
+            Some(format!("error[E0425]: cannot find value `db` (attempt {})\n --> src/main.rs:3:5", attempt))
+        );
+        let prompt = extend_ai_functions(print_fixed_code, &msg_context);
+        vec![
+            Message { role: "user".to_string(), content: prompt.content },
+            Message { role: "assistant".to_string(), content: format!("{{\"edits\": [], \"files\": [{{\"path\": \"src/db.rs\", \"contents\": \"fix {}\"}}]}}", attempt) },
+        ]
+    }
+
+    #[test]
+    fn test_context_window_by_prefix() {
+        assert_eq!(context_window("gpt-4-0613"), 8_192);
+        assert_eq!(context_window("gpt-4o-mini"), 128_000);
+        assert_eq!(context_window("claude-3-5-sonnet-20241022"), 200_000);
+        assert_eq!(context_window("llama3"), DEFAULT_CONTEXT_WINDOW);
+    }
+
+    #[test]
+    fn test_history_keeps_newest_turns_and_summarizes_the_rest() {
+        let memory: Vec<Message> = (0..10).flat_map(turn).collect();
+        let everything = select_history(&memory, 100_000);
+        assert_eq!(everything.len(), 20);
+
+        let budget = estimate_tokens(&memory) / 2;
+        let history = select_history(&memory, budget);
+        assert!(estimate_tokens(&history) <= budget);
+        assert_eq!(history[0].role, "system");
+        // the summary says which errors the dropped attempts were about and what came back
+        let summary = &history[0].content;
+        let dropped = 10 - (history.len() - 1) / 2;
+        assert!(summary.contains(&format!("- user: print_fixed_code on \"error[E0425]: cannot find value `db` (attempt {})", dropped - 1)));
+        assert!(summary.contains(&format!("\"contents\": \"fix {}\"", dropped - 1)));
+        assert!(!summary.contains("FUNCTION"));
+        // whole turns, ending with the newest reply
+        assert_eq!(history.len() % 2, 1);
+        assert!(history.last().unwrap().content.contains("fix 9"));
+    }
+
+    #[test]
+    fn test_history_is_capped_and_remembers_file_paths_only() {
+        let prompt = Message { role: "system".to_string(), content: "hi".to_string() };
+        assert_eq!(history_budget("claude-3-5-sonnet-20241022", &prompt, None), DEFAULT_HISTORY_TOKENS);
+        assert_eq!(history_budget("claude-3-5-sonnet-20241022", &prompt, Some(20_000)), 20_000);
+        assert!(history_budget("gpt-4", &prompt, Some(20_000)) < 8_192);
+
+        let project = "BACKEND_CODE: FILE: Cargo.toml\n```\n[package]\nname = \"app\"\n```\n\nFILE: src/main.rs\n```\nfn main() {}\n```\n ERROR_BUGS: None";
+        assert_eq!(
+            without_file_contents(project),
+            "BACKEND_CODE: FILE: Cargo.toml (contents left out)\n\nFILE: src/main.rs (contents left out)\n ERROR_BUGS: None"
+        );
+    }
+}
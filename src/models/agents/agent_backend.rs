@@ -1,34 +1,54 @@
 use crate::{
+    apis::client::client_context,
     ai_functions::aifunc_backend::{
         print_backend_webserver_code, print_fixed_code, print_improved_webserver_code,
         print_rest_api_endpoints,
     },
+    errors::{BotError, BotResult},
     helpers::{
-        command_line::{confirm_safe_to_proceed, PrintCommand},
+        command_line::{confirm_safe_to_proceed, ApprovePolicy, PrintCommand},
+        contract_test::run_contract_tests,
+        dependencies::{dependency_policy, write_cargo_config},
+        diagnostics::{apply_machine_applicable, format_for_llm, run_cargo_with_diagnostics},
+        history::{commit_revision, CodeHistory, Revision, LAST_BUILDING_TAG},
+        lint::{lint_policy, run_clippy, run_rustfmt},
+        server::ServerUnderTest,
         general::{
-            ai_task_request, check_status_code, read_code_template, read_exec_main_contents,
-            save_backend_code,
+            ai_task_request_decoded, read_backend_project, read_code_template, save_api_endpoints, save_backend_edits,
+            save_backend_files, save_contract_report,
         },
+        manifest::FileManifest,
+        patch::EditPlan,
+    },
+    models::{
+        agent_basic::basic_agent::{AgentState, BasicAgent},
+        general::workspace::Workspace,
     },
-    models::agent_basic::basic_agent::{AgentState, BasicAgent},
 };
 use async_trait::async_trait;
-use std::{
-    process::{Command, Stdio},
-    time,
-};
+use std::time::Duration;
 
-use super::agent_traits::{FactSheet, RouteObject, SpecialFunctions};
+use super::agent_traits::{AgentCheckpoint, FactSheet, RouteObject, SpecialFunctions};
+
+// Address the generated webserver listens on when the user runs it. Under test it gets a free
+// port through the PORT environment variable instead.
+pub const BACKEND_BASE_URL: &str = "http://localhost:1337";
+// First `cargo run` after a build still has to link, so be generous
+const SERVER_READY_TIMEOUT: Duration = Duration::from_secs(60);
+// Edit plans with conflicts sent back to the model before it is asked for whole files
+const MAX_EDIT_ATTEMPTS: u8 = 2;
 
 #[derive(Debug)]
 pub struct AgentBackendDeveloper {
     attributes: BasicAgent,
     bug_errors: Option<String>,
     bug_count: u8,
+    workspace: Workspace,
+    approve: ApprovePolicy,
 }
 
 impl AgentBackendDeveloper {
-    pub fn new() -> Self {
+    pub fn new(workspace: Workspace, approve: ApprovePolicy) -> Self {
         let attributes = BasicAgent {
             objective: "Develops the backend code for the webserver and mongodb database"
                 .to_string(),
@@ -40,69 +60,132 @@ impl AgentBackendDeveloper {
             attributes: attributes,
             bug_errors: None,
             bug_count: 0,
+            workspace,
+            approve,
         }
     }
-    async fn call_initial_backend_code(&mut self, factsheet: &mut FactSheet) {
-        let code_template = read_code_template();
+    async fn call_initial_backend_code(&mut self, factsheet: &mut FactSheet) -> BotResult<()> {
+        let code_template = read_code_template(&self.workspace)?;
         let msg_context = format!(
-            "CODE TEMPLATE: {} \n PROJECT_DESCRIPTION: {} \n",
-            code_template, factsheet.project_description
+            "CODE TEMPLATE: {} \n PROJECT_DESCRIPTION: {} \n ALLOWED_DEPENDENCIES:\n{} \n",
+            code_template, factsheet.project_description, dependency_policy().prompt_list()
         );
-        let ai_response = ai_task_request(
+        let manifest: FileManifest = ai_task_request_decoded(
             msg_context,
-            &self.attributes.position,
+            &mut self.attributes,
             get_function_string!(print_backend_webserver_code),
             print_backend_webserver_code,
         )
-        .await;
-        save_backend_code(&ai_response);
-        factsheet.backend_code = Some(ai_response);
+        .await?;
+        save_backend_files(&self.workspace, &manifest, &Revision {
+            agent: &self.attributes.position,
+            ai_function: get_function_string!(print_backend_webserver_code),
+            iteration: self.bug_count,
+        })?;
+        factsheet.backend_code = Some(read_backend_project(&self.workspace)?);
+        Ok(())
     }
-    async fn call_improved_backend_code(&mut self, factsheet: &mut FactSheet) {
-        let code_template = read_code_template();
+    // Asks for an edit plan and applies it. Plans that do not apply go back to the model with the
+    // conflicts; after MAX_EDIT_ATTEMPTS of those it has to rewrite the files it changes instead.
+    async fn call_backend_edits(
+        &mut self,
+        factsheet: &mut FactSheet,
+        msg_context: String,
+        ai_function: &str,
+        function_pass: fn(&str) -> &'static str,
+    ) -> BotResult<()> {
+        let mut context = msg_context.clone();
+        let mut attempt = 1;
+        loop {
+            let plan: EditPlan = ai_task_request_decoded(context, &mut self.attributes, ai_function, function_pass).await?;
+            let revision = Revision { agent: &self.attributes.position, ai_function, iteration: self.bug_count };
+            match save_backend_edits(&self.workspace, &plan, &revision) {
+                Ok(()) => break,
+                Err(e @ (BotError::PatchConflict { .. } | BotError::UnsafePath { .. } | BotError::DependencyRejected { .. }))
+                    if attempt <= MAX_EDIT_ATTEMPTS =>
+                {
+                    PrintCommand::Issue.print_agent_message(&self.attributes.position, &e.to_string());
+                    let instruction = if attempt == MAX_EDIT_ATTEMPTS {
+                        "Leave edits empty and print the complete new contents of every file you change in files."
+                    } else {
+                        "Copy every search text exactly from the current files."
+                    };
+                    context = format!("{}\nYOUR PREVIOUS EDITS WERE NOT APPLIED, NOTHING WAS CHANGED: {}\n{}", msg_context, e, instruction);
+                    attempt += 1;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+        factsheet.backend_code = Some(read_backend_project(&self.workspace)?);
+        Ok(())
+    }
+    async fn call_improved_backend_code(&mut self, factsheet: &mut FactSheet) -> BotResult<()> {
         let msg_context = format!(
-            "CODE TEMPLATE: {:?} \n PROJECT_DESCRIPTION: {:?} \n",
-            factsheet.backend_code, factsheet
+            "CODE TEMPLATE: {} \n PROJECT_DESCRIPTION: {:?} \n ALLOWED_DEPENDENCIES:\n{} \n",
+            factsheet.backend_code.clone().unwrap_or_default(), factsheet, dependency_policy().prompt_list()
         );
-        let ai_response = ai_task_request(
+        self.call_backend_edits(
+            factsheet,
             msg_context,
-            &self.attributes.position,
             get_function_string!(print_improved_webserver_code),
             print_improved_webserver_code,
         )
-        .await;
-        save_backend_code(&ai_response);
-        factsheet.backend_code = Some(ai_response);
+        .await
     }
 
-    async fn call_fix_code_bugs(&mut self, factsheet: &mut FactSheet) {
-        let code_template = read_code_template();
+    async fn call_fix_code_bugs(&mut self, factsheet: &mut FactSheet) -> BotResult<()> {
         let msg_context = format!(
-            "BROKEN_CODE: {:?} \n ERROR_BUGS: {:?} \n
-            THIS FUNCTION ONLY OUTPUTS CODE. JUST OUTPUT THE CODE.",
-            factsheet.backend_code, self.bug_errors
+            "BROKEN_CODE: {} \n ERROR_BUGS: {:?} \n ALLOWED_DEPENDENCIES:\n{} \n
+            THIS FUNCTION ONLY OUTPUTS THE JSON EDIT PLAN.",
+            factsheet.backend_code.clone().unwrap_or_default(), self.bug_errors, dependency_policy().prompt_list()
         );
-        let ai_response = ai_task_request(
+        self.call_backend_edits(
+            factsheet,
             msg_context,
-            &self.attributes.position,
             get_function_string!(print_fixed_code),
             print_fixed_code,
         )
-        .await;
-        save_backend_code(&ai_response);
-        factsheet.backend_code = Some(ai_response);
+        .await
+    }
+    // rustfmt, then clippy with the denied lint groups as errors. Returns the denied lints for the
+    // fix loop, if any. A missing tool is reported and skipped rather than failing the run.
+    fn run_lint_gate(&self, factsheet: &mut FactSheet, history: &CodeHistory) -> BotResult<Option<String>> {
+        let policy = lint_policy();
+        let root = self.workspace.root();
+        if policy.rustfmt {
+            if run_rustfmt(root)? {
+                let revision = Revision { agent: &self.attributes.position, ai_function: "cargo_fmt", iteration: self.bug_count };
+                if commit_revision(root, &revision)?.is_some() {
+                    // formatting does not change whether it builds
+                    history.tag(LAST_BUILDING_TAG)?;
+                    factsheet.backend_code = Some(read_backend_project(&self.workspace)?);
+                }
+            } else {
+                PrintCommand::Issue.print_agent_message(&self.attributes.position, "cargo fmt failed or is not installed, code left unformatted");
+            }
+        }
+        let Some(lints) = run_clippy(root, &policy)? else {
+            PrintCommand::Issue.print_agent_message(&self.attributes.position, "cargo clippy is not installed, skipping the lint gate");
+            return Ok(None);
+        };
+        factsheet.lint_count = Some(lints.lint_count);
+        let lint_msg = format!("Backend code lint: {} lints, {} denied", lints.lint_count, lints.denied.len());
+        PrintCommand::UnitTest.print_agent_message(&self.attributes.position, &lint_msg);
+        if lints.denied.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(format!("CLIPPY LINTS THAT MUST BE FIXED ({}):\n{}", policy.deny.join(", "), format_for_llm(root, &lints.denied))))
     }
-    async fn call_exact_rest_api_endpoints(&self) -> String {
-        let backend_code = read_exec_main_contents();
+    async fn call_exact_rest_api_endpoints(&mut self) -> BotResult<Vec<RouteObject>> {
+        let backend_code = read_backend_project(&self.workspace)?;
         let msg_context = format!("CODE_INPUT: {:?} \n", backend_code);
-        let ai_response = ai_task_request(
+        ai_task_request_decoded(
             msg_context,
-            &self.attributes.position,
+            &mut self.attributes,
             get_function_string!(print_rest_api_endpoints),
             print_rest_api_endpoints,
         )
-        .await;
-        ai_response
+        .await
     }
 }
 
@@ -111,119 +194,163 @@ impl SpecialFunctions for AgentBackendDeveloper {
     fn get_attributes_from_agents(&self) -> &BasicAgent {
         &self.attributes
     }
-    async fn execute(
-        &mut self,
-        factsheet: &mut FactSheet,
-    ) -> Result<(), Box<dyn std::error::Error>> {
-        while self.attributes.state != AgentState::Finished {
-            match &self.attributes.state {
-                AgentState::Discovery => {
-                    self.call_initial_backend_code(factsheet).await;
-                    self.attributes.state = AgentState::Working;
-                    continue;
+    fn get_checkpoint(&self) -> AgentCheckpoint {
+        AgentCheckpoint {
+            position: self.attributes.position.clone(),
+            state: self.attributes.state.clone(),
+            bug_count: self.bug_count,
+            bug_errors: self.bug_errors.clone(),
+            memory: self.attributes.memory.clone(),
+        }
+    }
+    fn restore_checkpoint(&mut self, checkpoint: &AgentCheckpoint) {
+        self.attributes.state = checkpoint.state.clone();
+        self.attributes.memory = checkpoint.memory.clone();
+        self.bug_count = checkpoint.bug_count;
+        self.bug_errors = checkpoint.bug_errors.clone();
+    }
+    // Failing integration tests go through the same fix loop as compiler errors
+    fn report_bugs(&mut self, bugs: &str) -> bool {
+        self.bug_count += 1;
+        self.bug_errors = Some(bugs.to_string());
+        self.attributes.state = AgentState::Working;
+        true
+    }
+    async fn step(&mut self, factsheet: &mut FactSheet) -> Result<(), BotError> {
+        match &self.attributes.state {
+            AgentState::Discovery => {
+                self.call_initial_backend_code(factsheet).await?;
+                self.attributes.state = AgentState::Working;
+            }
+            AgentState::Working => {
+                if self.bug_count == 0 {
+                    self.call_improved_backend_code(factsheet).await?;
+                } else {
+                    self.call_fix_code_bugs(factsheet).await?;
                 }
-                AgentState::Working => {
-                    if self.bug_count == 0 {
-                        self.call_improved_backend_code(factsheet).await;
-                    } else {
-                        self.call_fix_code_bugs(factsheet).await;
-                    }
-                    self.attributes.state = AgentState::UnitTesting;
-                    continue;
+                self.attributes.state = AgentState::UnitTesting;
+            }
+            AgentState::UnitTesting => {
+                PrintCommand::UnitTest.print_agent_message(
+                    &self.attributes.position,
+                    "Backend code unit testing",
+                );
+                let is_safe_to_proceed = confirm_safe_to_proceed(self.approve);
+                if !is_safe_to_proceed {
+                    return Err(BotError::UserAbort(
+                        "declined to build and run the generated backend".to_string(),
+                    ));
                 }
-                AgentState::UnitTesting => {
+                PrintCommand::UnitTest.print_agent_message(
+                    &self.attributes.position,
+                    "Backend code unit testing: building web server...",
+                );
+                write_cargo_config(&self.workspace, &dependency_policy())?;
+                let mut build = run_cargo_with_diagnostics(self.workspace.root(), &["build"])?;
+                if !build.success && apply_machine_applicable(self.workspace.root(), &build.diagnostics)? > 0 {
+                    // compiler suggested fixes are free, try them before asking the LLM
                     PrintCommand::UnitTest.print_agent_message(
                         &self.attributes.position,
-                        "Backend code unit testing",
+                        "Backend code unit testing: applied compiler suggestions, rebuilding...",
                     );
-                    let is_safe_to_proceed = confirm_safe_to_proceed();
-                    if !is_safe_to_proceed {
-                        panic!("Work on AI alignment");
-                    }
+                    factsheet.backend_code = Some(read_backend_project(&self.workspace)?);
+                    commit_revision(self.workspace.root(), &Revision {
+                        agent: &self.attributes.position,
+                        ai_function: "apply_machine_applicable",
+                        iteration: self.bug_count,
+                    })?;
+                    build = run_cargo_with_diagnostics(self.workspace.root(), &["build"])?;
+                }
+
+                let history = CodeHistory::init(self.workspace.root())?;
+                if build.success {
+                    history.tag(LAST_BUILDING_TAG)?;
                     PrintCommand::UnitTest.print_agent_message(
                         &self.attributes.position,
-                        "Backend code unit testing: building web server...",
+                        "Backend code unit testing: web server built successfully",
                     );
-                    let build_backend_server = Command::new("cargo")
-                        .arg("build")
-                        .current_dir("/home/ssa006/data_sync/")
-                        .stdout(Stdio::piped())
-                        .stderr(Stdio::piped())
-                        .output()
-                        .expect("failed to execute process");
-
-                    if build_backend_server.status.success() {
-                        self.bug_count = 0;
-                        PrintCommand::UnitTest.print_agent_message(
-                            &self.attributes.position,
-                            "Backend code unit testing: web server built successfully",
+                } else {
+                    let mut error_str = format_for_llm(self.workspace.root(), &build.diagnostics);
+                    if error_str.is_empty() {
+                        // failed before rustc ran, e.g. a bad Cargo.toml
+                        error_str = build.stderr;
+                    }
+                    // Once a revision has built, every fix starts from one, so a fix that does not
+                    // build made things worse: go back and keep working on the earlier problem
+                    if let Some(last_building) = history.tagged(LAST_BUILDING_TAG)? {
+                        let message = format!("{}: roll back to last building revision {:.8}", self.attributes.position, last_building);
+                        PrintCommand::Issue.print_agent_message(&self.attributes.position, &message);
+                        history.restore(self.workspace.root(), &last_building, &message)?;
+                        factsheet.backend_code = Some(read_backend_project(&self.workspace)?);
+                        error_str = format!(
+                            "{}\nTHE PREVIOUS FIX DID NOT COMPILE AND WAS ROLLED BACK. ITS ERRORS WERE:\n{}",
+                            self.bug_errors.clone().unwrap_or_default(),
+                            error_str
                         );
-                    } else {
-                        let error_arr: Vec<u8> = build_backend_server.stderr;
-                        let error_str = String::from_utf8(error_arr).unwrap();
-                        self.bug_count += 1;
-                        self.bug_errors = Some(error_str);
-                        if self.bug_count > 2 {
-                            panic!("Too many bugs");
-                        }
-                        self.attributes.state = AgentState::Working;
-                        continue;
                     }
+                    self.bug_count += 1;
+                    self.bug_errors = Some(error_str.clone());
+                    if self.bug_count > 2 {
+                        return Err(BotError::BuildFailure {
+                            attempts: self.bug_count,
+                            errors: error_str,
+                        });
+                    }
+                    self.attributes.state = AgentState::Working;
+                    return Ok(());
+                }
 
-                    let api_endpoints = self.call_exact_rest_api_endpoints().await;
-                    let api_endpoints: Vec<RouteObject> = serde_json::from_str(&api_endpoints)
-                        .expect("Failed to decode api endpoints");
-                    let check_endpoints: Vec<RouteObject> = api_endpoints
-                        .iter()
-                        .filter(|&route_object| {
-                            route_object.method == "get" && route_object.is_route_dynamic == "false"
-                        })
-                        .cloned()
-                        .collect();
-                    factsheet.api_endpoints_schema = Some(check_endpoints.clone());
-                    // run backend application
-                    PrintCommand::UnitTest.print_agent_message(
-                        &self.attributes.position,
-                        "Backend Code Unit Testing: Running web server...",
-                    );
-                    let mut run_backend_server = Command::new("cargo")
-                        .arg("run")
-                        .current_dir("/home/ssa006/data_sync/")
-                        .stdout(Stdio::piped())
-                        .stderr(Stdio::piped())
-                        .spawn()
-                        .expect("failed to run backend application");
+                if let Some(lint_errors) = self.run_lint_gate(factsheet, &history)? {
+                    self.bug_count += 1;
+                    self.bug_errors = Some(lint_errors.clone());
+                    if self.bug_count > 2 {
+                        return Err(BotError::LintFailure { attempts: self.bug_count, lints: lint_errors });
+                    }
+                    self.attributes.state = AgentState::Working;
+                    return Ok(());
+                }
 
-                    PrintCommand::UnitTest.print_agent_message(
-                        &self.attributes.position,
-                        "Backend Code Unit Testing: Launching tests on server in 5 seconds",
-                    );
-                    tokio::time::sleep(time::Duration::from_secs(5)).await;
+                let api_endpoints = self.call_exact_rest_api_endpoints().await?;
+                let api_endpoints_str = serde_json::to_string_pretty(&api_endpoints).unwrap_or_default();
+                save_api_endpoints(&self.workspace, &api_endpoints_str)?;
+                factsheet.api_endpoints_schema = Some(api_endpoints.clone());
+                // run backend application
+                let mut server = ServerUnderTest::start(self.workspace.root(), &self.workspace.server_log_path())?;
+                let running_msg = format!("Backend Code Unit Testing: Running web server on port {}...", server.port());
+                PrintCommand::UnitTest.print_agent_message(&self.attributes.position, &running_msg);
+                let testing_msg = format!("Testing {} endpoints against their schema", api_endpoints.len());
+                // Ctrl-C drops the server, which stops its whole process group
+                let report = tokio::select! {
+                    report_res = async {
+                        server.wait_until_ready(SERVER_READY_TIMEOUT).await?;
+                        PrintCommand::UnitTest.print_agent_message(&self.attributes.position, &testing_msg);
+                        Ok::<_, BotError>(run_contract_tests(client_context()?.http(), &server.base_url(), &api_endpoints).await?)
+                    } => report_res,
+                    _ = tokio::signal::ctrl_c() => Err(BotError::UserAbort("interrupted while testing the backend".to_string())),
+                };
+                server.stop().await;
+                let report = report?;
+                save_contract_report(&self.workspace, &report)?;
+                PrintCommand::UnitTest.print_agent_message(&self.attributes.position, &report.summary());
 
-                    for endpoint in check_endpoints {
-                        let testing_msg = format!(
-                            "Testing endpoint {} with method {}",
-                            endpoint.route, endpoint.method
-                        );
-                        PrintCommand::UnitTest
-                            .print_agent_message(&self.attributes.position, &testing_msg);
-                        let url = format!("http://localhost:1337{}", endpoint.route);
-                        match check_status_code(&url).await {
-                            Ok(status_code) => {
-                                if status_code != 200 {
-                                    panic!("Failed to get status code 200");
-                                }
-                            } ,
-                            Err(_e) => {
-                                panic!("Failed to get status code 200"); 
-                            },
-                        }
+                if !report.all_passed() {
+                    // failing routes go back through the fix loop like compiler errors do
+                    let error_str = format!("API CONTRACT TEST FAILURES:\n{}", report.summary());
+                    self.bug_count += 1;
+                    self.bug_errors = Some(error_str.clone());
+                    if self.bug_count > 2 {
+                        let route = report.failures().first().map(|r| r.route.clone()).unwrap_or_default();
+                        return Err(BotError::EndpointTest { route, message: error_str });
                     }
-
-                    self.attributes.state = AgentState::Finished;
+                    self.attributes.state = AgentState::Working;
+                    return Ok(());
                 }
-                _ => {}
+
+                self.bug_count = 0;
+                self.bug_errors = None;
+                self.attributes.state = AgentState::Finished;
             }
+            _ => {}
         }
         Ok(())
     }
@@ -0,0 +1,324 @@
+// Frontend Developer
+
+use async_trait::async_trait;
+
+use crate::{
+    ai_functions::aifunc_frontend::{print_fixed_frontend_code, print_frontend_client_code},
+    errors::{BotError, BotResult},
+    helpers::{
+        command_line::PrintCommand,
+        general::{ai_task_request_code, save_frontend_code},
+        history::Revision,
+    },
+    models::{
+        agent_basic::basic_agent::{AgentState, BasicAgent},
+        general::workspace::Workspace,
+    },
+};
+
+use super::{
+    agent_backend::BACKEND_BASE_URL,
+    agent_traits::{AgentCheckpoint, FactSheet, RouteObject, SpecialFunctions},
+};
+
+#[derive(Debug)]
+pub struct AgentFrontendDeveloper {
+    attributes: BasicAgent,
+    bug_errors: Option<String>,
+    bug_count: u8,
+    workspace: Workspace,
+}
+
+// First argument of every fetch() call, as written in the page
+fn fetch_targets(code: &str) -> Vec<&str> {
+    let mut targets = vec![];
+    for (idx, call) in code.match_indices("fetch(") {
+        let rest = &code[idx + call.len()..];
+        let (mut depth, mut quote, mut escaped) = (0, None, false);
+        let mut end = rest.len();
+        for (i, c) in rest.char_indices() {
+            match (quote, c) {
+                _ if escaped => escaped = false,
+                (Some(_), '\\') => escaped = true,
+                (Some(q), c) if c == q => quote = None,
+                (Some(_), _) => {}
+                (None, '\'' | '"' | '`') => quote = Some(c),
+                (None, '(' | '[' | '{') => depth += 1,
+                (None, ')' | ']' | '}') if depth > 0 => depth -= 1,
+                (None, ')' | ',') => {
+                    end = i;
+                    break;
+                }
+                _ => {}
+            }
+        }
+        targets.push(&rest[..end]);
+    }
+    targets
+}
+
+// The path a fetch target calls. String literals are kept, anything computed becomes "{}";
+// the base url in front and the query string are dropped. `BASE + '/item/' + id` -> "/item/{}".
+// None when no part of the path is written out, as in `fetch(url)`.
+fn fetched_path(target: &str) -> Option<String> {
+    fn computed(path: &mut String) {
+        if !path.ends_with("{}") {
+            path.push_str("{}");
+        }
+    }
+    let mut path = String::new();
+    let mut chars = target.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\'' | '"' | '`' => {
+                let quote = c;
+                while let Some(c) = chars.next() {
+                    match c {
+                        _ if c == quote => break,
+                        '\\' => {
+                            chars.next();
+                        }
+                        // ${...} in a template literal
+                        '$' if quote == '`' && chars.peek() == Some(&'{') => {
+                            let mut depth = 0;
+                            for c in chars.by_ref() {
+                                match c {
+                                    '{' => depth += 1,
+                                    '}' if depth == 1 => break,
+                                    '}' => depth -= 1,
+                                    _ => {}
+                                }
+                            }
+                            computed(&mut path);
+                        }
+                        _ => path.push(c),
+                    }
+                }
+            }
+            c if c.is_whitespace() || c == '+' => {}
+            _ => computed(&mut path),
+        }
+    }
+    let path = match path.find("://") {
+        Some(scheme) => path[scheme + 3..].find('/').map_or("/".to_string(), |p| path[scheme + 3 + p..].to_string()),
+        None => path,
+    };
+    let path = path.split(['?', '#']).next().unwrap_or_default().trim_start_matches("{}");
+    (!path.is_empty()).then(|| path.to_string())
+}
+
+// Whether a fetched path calls the route. "{...}" segments of the route match any segment.
+fn calls_route(path: &str, route: &str) -> bool {
+    let segments = |p: &str| p.trim_matches('/').split('/').map(str::to_string).collect::<Vec<String>>();
+    let (path, route) = (segments(path), segments(route));
+    path.len() == route.len()
+        && path.iter().zip(&route).all(|(p, r)| if r.starts_with('{') { !p.is_empty() } else { p == r })
+}
+
+// Checks the generated client is a self-contained page that calls every backend route.
+// Returns the list of problems found, empty when the page passes.
+pub fn verify_frontend_code(code: &str, routes: &[RouteObject]) -> Vec<String> {
+    let mut problems = vec![];
+    let lower = code.to_lowercase();
+    if !lower.contains("<html") {
+        problems.push("output is not an HTML document (no <html> tag)".to_string());
+    }
+    if !lower.contains("<script") {
+        problems.push("no <script> tag, the page cannot call the backend".to_string());
+    }
+    if !code.contains("fetch(") {
+        problems.push("no fetch() calls to the backend".to_string());
+    }
+    if lower.contains("<script src=\"http") || lower.contains("<link href=\"http") {
+        problems.push("external scripts or stylesheets are not allowed".to_string());
+    }
+    let fetched: Vec<String> = fetch_targets(code).into_iter().filter_map(fetched_path).collect();
+    for route in routes {
+        if !fetched.iter().any(|path| calls_route(path, &route.route)) {
+            problems.push(format!("route {} {} is never called", route.method, route.route));
+        }
+    }
+    problems
+}
+
+impl AgentFrontendDeveloper {
+    pub fn new(workspace: Workspace) -> Self {
+        let attributes = BasicAgent {
+            objective: "Develops a static web client for the generated backend".to_string(),
+            position: "Frontend Developer".to_string(),
+            state: AgentState::Discovery,
+            memory: vec![],
+        };
+        Self {
+            attributes,
+            bug_errors: None,
+            bug_count: 0,
+            workspace,
+        }
+    }
+    async fn call_initial_frontend_code(&mut self, factsheet: &mut FactSheet) -> BotResult<()> {
+        let msg_context = format!(
+            "PROJECT_DESCRIPTION: {} \n BACKEND_URL: {} \n API_ENDPOINTS: {} \n",
+            factsheet.project_description,
+            BACKEND_BASE_URL,
+            serde_json::to_string(&factsheet.api_endpoints_schema).unwrap_or_default()
+        );
+        let ai_response = ai_task_request_code(
+            msg_context,
+            &mut self.attributes,
+            get_function_string!(print_frontend_client_code),
+            print_frontend_client_code,
+        )
+        .await?;
+        save_frontend_code(&self.workspace, &ai_response, &Revision {
+            agent: &self.attributes.position,
+            ai_function: get_function_string!(print_frontend_client_code),
+            iteration: self.bug_count,
+        })?;
+        factsheet.frontend_code = Some(ai_response);
+        Ok(())
+    }
+    async fn call_fix_frontend_bugs(&mut self, factsheet: &mut FactSheet) -> BotResult<()> {
+        let msg_context = format!(
+            "BROKEN_CODE: {:?} \n API_ENDPOINTS: {} \n ERROR_BUGS: {:?} \n
+            THIS FUNCTION ONLY OUTPUTS CODE. JUST OUTPUT THE CODE.",
+            factsheet.frontend_code,
+            serde_json::to_string(&factsheet.api_endpoints_schema).unwrap_or_default(),
+            self.bug_errors
+        );
+        let ai_response = ai_task_request_code(
+            msg_context,
+            &mut self.attributes,
+            get_function_string!(print_fixed_frontend_code),
+            print_fixed_frontend_code,
+        )
+        .await?;
+        save_frontend_code(&self.workspace, &ai_response, &Revision {
+            agent: &self.attributes.position,
+            ai_function: get_function_string!(print_fixed_frontend_code),
+            iteration: self.bug_count,
+        })?;
+        factsheet.frontend_code = Some(ai_response);
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl SpecialFunctions for AgentFrontendDeveloper {
+    fn get_attributes_from_agents(&self) -> &BasicAgent {
+        &self.attributes
+    }
+    fn get_checkpoint(&self) -> AgentCheckpoint {
+        AgentCheckpoint {
+            position: self.attributes.position.clone(),
+            state: self.attributes.state.clone(),
+            bug_count: self.bug_count,
+            bug_errors: self.bug_errors.clone(),
+            memory: self.attributes.memory.clone(),
+        }
+    }
+    fn restore_checkpoint(&mut self, checkpoint: &AgentCheckpoint) {
+        self.attributes.state = checkpoint.state.clone();
+        self.attributes.memory = checkpoint.memory.clone();
+        self.bug_count = checkpoint.bug_count;
+        self.bug_errors = checkpoint.bug_errors.clone();
+    }
+    async fn step(&mut self, factsheet: &mut FactSheet) -> Result<(), BotError> {
+        match &self.attributes.state {
+            AgentState::Discovery => {
+                if factsheet.api_endpoints_schema.is_none() {
+                    PrintCommand::Issue.print_agent_message(
+                        &self.attributes.position,
+                        "No API endpoint schema on the factsheet, skipping frontend",
+                    );
+                    self.attributes.state = AgentState::Finished;
+                    return Ok(());
+                }
+                self.call_initial_frontend_code(factsheet).await?;
+                self.attributes.state = AgentState::UnitTesting;
+            }
+            AgentState::Working => {
+                self.call_fix_frontend_bugs(factsheet).await?;
+                self.attributes.state = AgentState::UnitTesting;
+            }
+            AgentState::UnitTesting => {
+                PrintCommand::UnitTest.print_agent_message(
+                    &self.attributes.position,
+                    "Frontend code unit testing: checking client against API endpoints",
+                );
+                let code = factsheet.frontend_code.clone().unwrap_or_default();
+                let routes = factsheet.api_endpoints_schema.clone().unwrap_or_default();
+                let problems = verify_frontend_code(&code, &routes);
+                if problems.is_empty() {
+                    self.bug_count = 0;
+                    self.bug_errors = None;
+                    let done_msg = format!(
+                        "Frontend code unit testing: client written to {}",
+                        self.workspace.frontend_file.display()
+                    );
+                    PrintCommand::UnitTest.print_agent_message(&self.attributes.position, &done_msg);
+                    self.attributes.state = AgentState::Finished;
+                    return Ok(());
+                }
+                let error_str = problems.join("\n");
+                PrintCommand::Issue.print_agent_message(&self.attributes.position, &error_str);
+                self.bug_count += 1;
+                self.bug_errors = Some(error_str.clone());
+                if self.bug_count > 2 {
+                    return Err(BotError::BuildFailure {
+                        attempts: self.bug_count,
+                        errors: error_str,
+                    });
+                }
+                self.attributes.state = AgentState::Working;
+            }
+            _ => {
+                self.attributes.state = AgentState::Finished;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn route(method: &str, path: &str) -> RouteObject {
+        RouteObject {
+            is_route_dynamic: path.contains('{').to_string(),
+            method: method.to_string(),
+            request_body: serde_json::Value::Null,
+            response: serde_json::Value::Null,
+            route: path.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_verify_frontend_code() {
+        let routes = vec![route("get", "/task"), route("delete", "/task/{id}"), route("post", "/login")];
+        let page = "<!DOCTYPE html><html><body><script>
+            fetch(BASE + '/task'); fetch(`${BASE}/task/${id}`, { method: 'DELETE' });
+        </script></body></html>";
+        let problems = verify_frontend_code(page, &routes);
+        assert_eq!(problems, vec!["route post /login is never called".to_string()]);
+
+        // a route only counts when a fetch() calls it, not when it is a prefix or shows up elsewhere
+        let routes = vec![route("get", "/"), route("get", "/task"), route("get", "/tasks"), route("post", "/login"), route("put", "/task/{id}/done")];
+        let page = "<!DOCTYPE html><html><body><a href=\"/login\">Log in</a><script>
+            const BASE = 'http://localhost:8080';
+            fetch(BASE + \"/tasks?page=\" + page); fetch(`${BASE}/task/${id}/done`, { method: 'PUT', body: JSON.stringify({ done: true }) });
+        </script></body></html>";
+        let problems = verify_frontend_code(page, &routes);
+        assert_eq!(
+            problems,
+            vec!["route get / is never called".to_string(), "route get /task is never called".to_string(), "route post /login is never called".to_string()]
+        );
+        assert!(verify_frontend_code(&page.replace("fetch(BASE + \"/tasks", "fetch('http://localhost:8080/'); fetch(BASE + \"/tasks"), &routes[..1]).is_empty());
+        // a target with nothing written out could be anything, so it calls no route
+        assert!(!verify_frontend_code(&page.replace("fetch(BASE + \"/tasks", "fetch(url); fetch(url + '?page=2'); fetch(BASE + \"/tasks"), &routes[..1]).is_empty());
+
+        let problems = verify_frontend_code("Sure! Here is your page.", &routes);
+        assert!(problems.len() > routes.len());
+    }
+}
@@ -0,0 +1,226 @@
+// Test Engineer
+
+use async_trait::async_trait;
+
+use crate::{
+    ai_functions::aifunc_testing::{print_api_integration_tests, print_fixed_api_tests, print_wrong_tests},
+    errors::{BotError, BotResult},
+    helpers::{
+        cargo_test::{parse_test_results, TestReport},
+        command_line::{confirm_safe_to_proceed, ApprovePolicy, PrintCommand},
+        diagnostics::{format_for_llm, run_cargo_with_diagnostics},
+        general::{ai_task_request_code, ai_task_request_decoded, declare_test_module, read_backend_project, save_test_code, save_test_report},
+        history::{commit_revision, CodeHistory, Revision, LAST_BUILDING_TAG},
+    },
+    models::{
+        agent_basic::basic_agent::{AgentState, BasicAgent},
+        general::workspace::Workspace,
+    },
+};
+
+use super::agent_traits::{AgentCheckpoint, FactSheet, SpecialFunctions};
+
+#[derive(Debug)]
+pub struct AgentTestEngineer {
+    attributes: BasicAgent,
+    bug_errors: Option<String>,
+    bug_count: u8,
+    workspace: Workspace,
+    approve: ApprovePolicy,
+}
+
+impl AgentTestEngineer {
+    pub fn new(workspace: Workspace, approve: ApprovePolicy) -> Self {
+        let attributes = BasicAgent {
+            objective: "Writes and runs integration tests for every backend route".to_string(),
+            position: "Test Engineer".to_string(),
+            state: AgentState::Discovery,
+            memory: vec![],
+        };
+        Self {
+            attributes,
+            bug_errors: None,
+            bug_count: 0,
+            workspace,
+            approve,
+        }
+    }
+    async fn call_initial_tests(&mut self, factsheet: &mut FactSheet) -> BotResult<()> {
+        let msg_context = format!(
+            "PROJECT_DESCRIPTION: {} \n BACKEND_CODE: {} \n API_ENDPOINTS: {} \n",
+            factsheet.project_description,
+            read_backend_project(&self.workspace)?,
+            serde_json::to_string(&factsheet.api_endpoints_schema).unwrap_or_default()
+        );
+        let ai_response = ai_task_request_code(
+            msg_context,
+            &mut self.attributes,
+            get_function_string!(print_api_integration_tests),
+            print_api_integration_tests,
+        )
+        .await?;
+        save_test_code(&self.workspace, &ai_response, &Revision {
+            agent: &self.attributes.position,
+            ai_function: get_function_string!(print_api_integration_tests),
+            iteration: self.bug_count,
+        })
+    }
+    // Before failures go to the backend developer: which of them are the tests' own fault
+    async fn call_find_wrong_tests(&mut self, factsheet: &FactSheet, report: &TestReport) -> BotResult<Vec<String>> {
+        let msg_context = format!(
+            "TESTS: {} \n BACKEND_CODE: {} \n API_ENDPOINTS: {} \n TEST_FAILURES: {} \n",
+            std::fs::read_to_string(self.workspace.api_tests_path())?,
+            read_backend_project(&self.workspace)?,
+            serde_json::to_string(&factsheet.api_endpoints_schema).unwrap_or_default(),
+            report.summary()
+        );
+        let wrong_tests: Vec<String> = ai_task_request_decoded(
+            msg_context,
+            &mut self.attributes,
+            get_function_string!(print_wrong_tests),
+            print_wrong_tests,
+        )
+        .await?;
+        // only failing tests can be wrong, under the names libtest reported
+        Ok(report.failures_named(&wrong_tests).into_iter().map(|r| r.name.clone()).collect())
+    }
+    async fn call_fix_tests(&mut self) -> BotResult<()> {
+        let broken_tests = std::fs::read_to_string(self.workspace.api_tests_path())?;
+        let msg_context = format!(
+            "BROKEN_TESTS: {} \n BACKEND_CODE: {} \n ERROR_BUGS: {:?} \n
+            THIS FUNCTION ONLY OUTPUTS CODE. JUST OUTPUT THE CODE.",
+            broken_tests,
+            read_backend_project(&self.workspace)?,
+            self.bug_errors
+        );
+        let ai_response = ai_task_request_code(
+            msg_context,
+            &mut self.attributes,
+            get_function_string!(print_fixed_api_tests),
+            print_fixed_api_tests,
+        )
+        .await?;
+        save_test_code(&self.workspace, &ai_response, &Revision {
+            agent: &self.attributes.position,
+            ai_function: get_function_string!(print_fixed_api_tests),
+            iteration: self.bug_count,
+        })
+    }
+}
+
+#[async_trait]
+impl SpecialFunctions for AgentTestEngineer {
+    fn get_attributes_from_agents(&self) -> &BasicAgent {
+        &self.attributes
+    }
+    fn get_checkpoint(&self) -> AgentCheckpoint {
+        AgentCheckpoint {
+            position: self.attributes.position.clone(),
+            state: self.attributes.state.clone(),
+            bug_count: self.bug_count,
+            bug_errors: self.bug_errors.clone(),
+            memory: self.attributes.memory.clone(),
+        }
+    }
+    fn restore_checkpoint(&mut self, checkpoint: &AgentCheckpoint) {
+        self.attributes.state = checkpoint.state.clone();
+        self.attributes.memory = checkpoint.memory.clone();
+        self.bug_count = checkpoint.bug_count;
+        self.bug_errors = checkpoint.bug_errors.clone();
+    }
+    async fn step(&mut self, factsheet: &mut FactSheet) -> Result<(), BotError> {
+        match &self.attributes.state {
+            AgentState::Discovery => {
+                if factsheet.api_endpoints_schema.is_none() {
+                    PrintCommand::Issue.print_agent_message(
+                        &self.attributes.position,
+                        "No API endpoint schema on the factsheet, skipping integration tests",
+                    );
+                    self.attributes.state = AgentState::Finished;
+                    return Ok(());
+                }
+                self.call_initial_tests(factsheet).await?;
+                self.attributes.state = AgentState::UnitTesting;
+            }
+            AgentState::Working => {
+                self.call_fix_tests().await?;
+                self.attributes.state = AgentState::UnitTesting;
+            }
+            AgentState::UnitTesting => {
+                PrintCommand::UnitTest.print_agent_message(&self.attributes.position, "Running integration tests");
+                if !confirm_safe_to_proceed(self.approve) {
+                    return Err(BotError::UserAbort("declined to run the generated tests".to_string()));
+                }
+                // a rewrite of the main file by the backend developer may have dropped it
+                declare_test_module(&self.workspace)?;
+                let outcome = run_cargo_with_diagnostics(self.workspace.root(), &["test", "--no-fail-fast"])?;
+                let report = parse_test_results(&outcome.stdout);
+                if report.results.is_empty() {
+                    // the tests did not compile, which is this agent's problem to fix
+                    let mut error_str = format_for_llm(self.workspace.root(), &outcome.diagnostics);
+                    if error_str.is_empty() {
+                        error_str = if outcome.success { "the test module contains no tests".to_string() } else { outcome.stderr };
+                    }
+                    PrintCommand::Issue.print_agent_message(&self.attributes.position, "Integration tests did not run");
+                    self.bug_count += 1;
+                    self.bug_errors = Some(error_str.clone());
+                    if self.bug_count > 2 {
+                        return Err(BotError::BuildFailure { attempts: self.bug_count, errors: error_str });
+                    }
+                    self.attributes.state = AgentState::Working;
+                    return Ok(());
+                }
+                // the tests compiled, so this is the revision a failing backend fix rolls back to
+                commit_revision(self.workspace.root(), &Revision {
+                    agent: &self.attributes.position,
+                    ai_function: "run_integration_tests",
+                    iteration: self.bug_count,
+                })?;
+                CodeHistory::init(self.workspace.root())?.tag(LAST_BUILDING_TAG)?;
+                save_test_report(&self.workspace, &report)?;
+                let failures = report.failures();
+                let result_msg = format!("{} of {} integration tests passed", report.results.len() - failures.len(), report.results.len());
+                PrintCommand::UnitTest.print_agent_message(&self.attributes.position, &result_msg);
+                if report.all_passed() {
+                    self.bug_count = 0;
+                    self.bug_errors = None;
+                    self.attributes.state = AgentState::Finished;
+                    return Ok(());
+                }
+                let tests_path = self.workspace.api_tests_path();
+                let tests_path = tests_path.strip_prefix(self.workspace.root()).unwrap_or(&tests_path).display().to_string();
+                // tests that expect the wrong thing are fixed here first, the service is not to blame for them
+                let wrong_tests = self.call_find_wrong_tests(factsheet, &report).await?;
+                if !wrong_tests.is_empty() {
+                    let wrong: Vec<String> = failures
+                        .iter()
+                        .filter(|r| wrong_tests.contains(&r.name))
+                        .map(|r| format!("FAIL {}\n{}", r.name, r.output))
+                        .collect();
+                    let error_str = format!("TESTS THAT EXPECT THE WRONG THING (in {}):\n{}", tests_path, wrong.join("\n"));
+                    PrintCommand::Issue.print_agent_message(&self.attributes.position, &format!("Fixing {} wrong test(s) before blaming the backend", wrong_tests.len()));
+                    self.bug_count += 1;
+                    self.bug_errors = Some(error_str.clone());
+                    if self.bug_count > 2 {
+                        return Err(BotError::EndpointTest { route: wrong_tests[0].clone(), message: error_str });
+                    }
+                    self.attributes.state = AgentState::Working;
+                    return Ok(());
+                }
+                // confirmed failures go to the backend developer's repair loop, then they run again
+                let error_str = format!("INTEGRATION TEST FAILURES (tests in {}):\n{}", tests_path, report.summary());
+                self.bug_count += 1;
+                self.bug_errors = Some(error_str.clone());
+                if self.bug_count > 2 {
+                    let test = failures.first().map(|r| r.name.clone()).unwrap_or_default();
+                    return Err(BotError::EndpointTest { route: test, message: error_str });
+                }
+                factsheet.test_failures = Some(error_str);
+            }
+            _ => {
+                self.attributes.state = AgentState::Finished;
+            }
+        }
+        Ok(())
+    }
+}
@@ -2,21 +2,50 @@ use serde::{Deserialize, Serialize};
 use std::fmt::Debug;
 use async_trait::async_trait;
 
-use crate::models::agent_basic::basic_agent::BasicAgent;
+use crate::{errors::BotError, models::{agent_basic::basic_agent::{AgentState, BasicAgent}, general::{llm::Message, schema::{object_schema, JsonSchema}}}};
 #[derive(Serialize, Deserialize, Debug,Clone,PartialEq,Copy)]
 pub struct ProjectScope {
     pub is_crud_required: bool,
     pub is_user_login_and_logout: bool,
     pub is_external_urls_required: bool,
 }
+impl JsonSchema for ProjectScope {
+    fn json_schema() -> serde_json::Value {
+        object_schema(&[
+            ("is_crud_required", bool::json_schema()),
+            ("is_user_login_and_logout", bool::json_schema()),
+            ("is_external_urls_required", bool::json_schema()),
+        ])
+    }
+}
+// The prompts ask for every value as a string ("true"), models often answer with a bare bool
+fn bool_or_string<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<String, D::Error> {
+    match serde_json::Value::deserialize(deserializer)? {
+        serde_json::Value::Bool(b) => Ok(b.to_string()),
+        serde_json::Value::String(s) => Ok(s.to_lowercase()),
+        other => Err(serde::de::Error::custom(format!("expected a bool or string, got {}", other))),
+    }
+}
 #[derive(Serialize, Deserialize, Debug,Clone,PartialEq)]
 pub struct RouteObject {
+    #[serde(deserialize_with = "bool_or_string")]
     pub is_route_dynamic: String,
     pub method: String,
     pub request_body: serde_json::Value,
     pub response: serde_json::Value,
     pub route: String,
 }
+impl JsonSchema for RouteObject {
+    fn json_schema() -> serde_json::Value {
+        object_schema(&[
+            ("is_route_dynamic", serde_json::json!({ "type": "string", "enum": ["true", "false"] })),
+            ("method", serde_json::json!({ "type": "string", "enum": ["get", "post", "put", "patch", "delete"] })),
+            ("request_body", serde_json::Value::json_schema()),
+            ("response", serde_json::Value::json_schema()),
+            ("route", String::json_schema()),
+        ])
+    }
+}
 #[derive(Serialize, Deserialize, Debug,Clone,PartialEq)]
 pub struct FactSheet {
     pub project_description: String,
@@ -24,10 +53,38 @@ pub struct FactSheet {
     pub external_urls: Option<Vec<String>>,
     pub backend_code: Option<String>,
     pub api_endpoints_schema: Option<Vec<RouteObject>>,
+    pub frontend_code: Option<String>,
+    // failing integration tests, waiting to go back to the agent that wrote the code
+    #[serde(default)]
+    pub test_failures: Option<String>,
+    // warnings and errors clippy reported on the last backend revision it checked
+    #[serde(default)]
+    pub lint_count: Option<usize>,
+}
+
+// What an agent needs to carry on from where it stopped
+#[derive(Serialize, Deserialize, Debug,Clone,PartialEq)]
+pub struct AgentCheckpoint {
+    pub position: String,
+    pub state: AgentState,
+    pub bug_count: u8,
+    pub bug_errors: Option<String>,
+    // conversation so far, so a resumed fix loop still sees its earlier attempts
+    #[serde(default)]
+    pub memory: Vec<Message>,
 }
 
 #[async_trait]
 pub trait SpecialFunctions: Debug {
     fn get_attributes_from_agents(&self) -> &BasicAgent;
-    async fn execute(&mut self,factsheet: &mut FactSheet ) -> Result<(), Box<dyn std::error::Error>>;
+    fn get_checkpoint(&self) -> AgentCheckpoint;
+    fn restore_checkpoint(&mut self, checkpoint: &AgentCheckpoint);
+    // Performs a single state transition. The manager calls this until the agent is Finished,
+    // checkpointing in between.
+    async fn step(&mut self,factsheet: &mut FactSheet ) -> Result<(), BotError>;
+    // Bugs a later agent found in this agent's work. Returns true if the agent took them on and
+    // has to run again.
+    fn report_bugs(&mut self, _bugs: &str) -> bool {
+        false
+    }
 }
\ No newline at end of file
@@ -1,21 +1,31 @@
-use crate::{ai_functions::{aifunc_architect::print_project_scope, aifunc_managing::convert_user_input_to_goal}, helpers::general::ai_task_request};
+pub mod session;
 
-use super::{agent_basic::basic_agent::{BasicAgent, AgentState}, agents::{agent_traits::{FactSheet, SpecialFunctions}, agent_architect::AgentSolutionArchitect, agent_backend::AgentBackendDeveloper}};
+use crate::{ai_functions::aifunc_managing::convert_user_input_to_goal, errors::BotError, helpers::{command_line::{ApprovePolicy, PrintCommand}, general::ai_task_request, scaffold::scaffold_workspace}};
+
+use self::session::{Checkpoint, Session};
+use super::{general::{usage::{check_budget, restore_usage, usage_report}, workspace::Workspace}, agent_basic::basic_agent::{BasicAgent, AgentState}, agents::{agent_traits::{FactSheet, SpecialFunctions}, agent_architect::AgentSolutionArchitect, agent_backend::AgentBackendDeveloper, agent_frontend::AgentFrontendDeveloper, agent_test_engineer::AgentTestEngineer}};
 #[derive(Debug)]
 pub struct ManagingAgent {
     attributes: BasicAgent,
     factsheet: FactSheet,
-    agents: Vec<Box<dyn SpecialFunctions>>
+    agents: Vec<Box<dyn SpecialFunctions>>,
+    workspace: Workspace,
+    user_request: String,
+    session: Session,
+    approve: ApprovePolicy,
 }
 impl ManagingAgent {
-    pub async fn new(usr_req: String) -> Result<Self, Box<dyn std::error::Error>>  {
-        let attributes = BasicAgent {
+    fn manager_attributes() -> BasicAgent {
+        BasicAgent {
             objective: "Manage agents who are building an excellent software product".to_string(),
             position: "Project Manager".to_string(),
             state: AgentState::Discovery,
             memory: vec![],
-        };
-        let project_description = ai_task_request(usr_req, &attributes.position, get_function_string!(convert_user_input_to_goal), convert_user_input_to_goal).await;
+        }
+    }
+    pub async fn new(usr_req: String, workspace: Workspace, session: Session, approve: ApprovePolicy) -> Result<Self, BotError>  {
+        let mut attributes = Self::manager_attributes();
+        let project_description = ai_task_request(usr_req.clone(), &mut attributes, get_function_string!(convert_user_input_to_goal), convert_user_input_to_goal).await?;
         let agents: Vec<Box<dyn SpecialFunctions>> = vec![];
         let factsheet = FactSheet {
             project_description,
@@ -23,27 +33,252 @@ impl ManagingAgent {
             external_urls: None,
             backend_code: None,
             api_endpoints_schema: None,
+            frontend_code: None,
+            test_failures: None,
+            lint_count: None,
         };
-        Ok(Self {
+        let mut managing_agent = Self {
             attributes,
             factsheet,
-            agents
-        })
+            agents,
+            workspace,
+            user_request: usr_req,
+            session,
+            approve,
+        };
+        managing_agent.create_agent();
+        managing_agent.save_checkpoint()?;
+        Ok(managing_agent)
+    }
+    // Rebuilds the manager and its agents from the last checkpoint of a session
+    pub fn resume(session: Session, approve: ApprovePolicy) -> Result<Self, BotError> {
+        let checkpoint = session.load()?;
+        restore_usage(checkpoint.usage);
+        let mut managing_agent = Self {
+            attributes: Self::manager_attributes(),
+            factsheet: checkpoint.factsheet,
+            agents: vec![],
+            workspace: checkpoint.workspace,
+            user_request: checkpoint.user_request,
+            session,
+            approve,
+        };
+        managing_agent.create_agent();
+        for agent in managing_agent.agents.iter_mut() {
+            let position = agent.get_attributes_from_agents().position.clone();
+            if let Some(agent_checkpoint) = checkpoint.agents.iter().find(|a| a.position == position) {
+                agent.restore_checkpoint(agent_checkpoint);
+            }
+        }
+        Ok(managing_agent)
+    }
+    pub fn session_id(&self) -> &str {
+        &self.session.id
+    }
+    pub fn factsheet(&self) -> &FactSheet {
+        &self.factsheet
     }
     fn add_agent(&mut self, agent: Box<dyn SpecialFunctions>) {
         self.agents.push(agent);
     }
     fn create_agent(&mut self) {
         self.add_agent(Box::new(AgentSolutionArchitect::new()));
-        self.add_agent(Box::new(AgentBackendDeveloper::new()))
-        // add backend agent and frontend agent
+        self.add_agent(Box::new(AgentBackendDeveloper::new(self.workspace.clone(), self.approve)));
+        self.add_agent(Box::new(AgentTestEngineer::new(self.workspace.clone(), self.approve)));
+        self.add_agent(Box::new(AgentFrontendDeveloper::new(self.workspace.clone())))
+    }
+    fn save_checkpoint(&self) -> Result<(), BotError> {
+        let checkpoint = Checkpoint {
+            session_id: self.session.id.clone(),
+            user_request: self.user_request.clone(),
+            workspace: self.workspace.clone(),
+            factsheet: self.factsheet.clone(),
+            agents: self.agents.iter().map(|agent| agent.get_checkpoint()).collect(),
+            usage: usage_report(),
+        };
+        self.session.save(&checkpoint)
     }
-    pub async fn execute_project(&mut self)  {
-        self.create_agent();
-        for agent in self.agents.iter_mut() {
-            let agent_res =  agent.execute(&mut self.factsheet).await;
-            // let agent_info = agent.get_attributes_from_agents();
-            // dbg!(agent_info);
+    // Runs every agent in order and stops at the first one that fails or when the LLM budget
+    // runs out. The session is checkpointed after every agent state transition.
+    pub async fn execute_project(&mut self) -> Result<(), BotError> {
+        let run_res = self.run_agents().await;
+        PrintCommand::AICall.print_agent_message(&self.attributes.position, &usage_report().summary());
+        run_res
+    }
+    async fn run_agents(&mut self) -> Result<(), BotError> {
+        if scaffold_workspace(&self.workspace)? {
+            let scaffold_msg = format!("Scaffolded a new project in {}", self.workspace.root().display());
+            PrintCommand::AICall.print_agent_message(&self.attributes.position, &scaffold_msg);
+        }
+        self.attributes.state = AgentState::Working;
+        let mut idx = 0;
+        while idx < self.agents.len() {
+            if self.agents[idx].get_attributes_from_agents().state == AgentState::Finished {
+                idx += 1;
+                continue;
+            }
+            let step_res = self.agents[idx].step(&mut self.factsheet).await.and_then(|_| check_budget());
+            self.save_checkpoint()?;
+            if let Err(e) = step_res {
+                let agent_pos = self.agents[idx].get_attributes_from_agents().position.clone();
+                PrintCommand::Issue.print_agent_message(&agent_pos, &format!("[{}] {}", e.kind(), e));
+                return Err(e);
+            }
+            // Bugs found in an earlier agent's work send the run back to that agent. Everything
+            // after it, including the agent that found them, carries on from where it was.
+            if let Some(bugs) = self.factsheet.test_failures.take() {
+                let agent_pos = self.agents[idx].get_attributes_from_agents().position.clone();
+                let owner = self.agents[..idx].iter_mut().position(|agent| agent.report_bugs(&bugs));
+                let Some(owner) = owner else {
+                    return Err(BotError::EndpointTest { route: agent_pos, message: bugs });
+                };
+                let owner_pos = self.agents[owner].get_attributes_from_agents().position.clone();
+                PrintCommand::Issue.print_agent_message(&agent_pos, &format!("Tests are failing, handing them back to the {}", owner_pos));
+                self.save_checkpoint()?;
+                idx = owner;
+            }
         }
+        self.attributes.state = AgentState::Finished;
+        Ok(())
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::apis::{
+        cassette::CassetteProvider,
+        client::{set_client_context, ClientContext, HttpTransport},
+        llm_provider::{LlmProvider, ProviderConfig, ProviderKind},
+        retry::RetryPolicy,
+        routing::{ModelSettings, ModelsConfig},
+    };
+    use crate::errors::BotResult;
+    use crate::models::general::{
+        llm::{APIChoice, APIMessage, APIResponse, Message},
+        workspace::WorkspaceConfig,
+    };
+    use async_trait::async_trait;
+    use std::fs;
+
+    // The project the run starts from. It has no crates, so the generated backend builds offline.
+    const CARGO_TOML: &str = "[package]\nname = \"ping_service\"\nversion = \"0.1.0\"\nedition = \"2021\"\n\n[dependencies]\n";
+    const MAIN_RS: &str = r#"use std::io::{Read, Write};
+use std::net::TcpListener;
+
+// routes
+fn respond(request: &str) -> (u16, &'static str) {
+    if request.starts_with("GET /ping ") {
+        (200, "{\"message\":\"pong\"}")
+    } else {
+        (404, "{}")
+    }
+}
+
+fn main() {
+    let port = std::env::var("PORT").unwrap_or_else(|_| "1337".to_string());
+    let listener = TcpListener::bind(format!("127.0.0.1:{}", port)).unwrap();
+    for mut stream in listener.incoming().flatten() {
+        let mut buf = [0u8; 4096];
+        let n = stream.read(&mut buf).unwrap_or(0);
+        let (status, body) = respond(&String::from_utf8_lossy(&buf[..n]));
+        let _ = write!(stream, "HTTP/1.1 {} OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}", status, body.len(), body);
+    }
+}
+"#;
+    const API_TESTS_RS: &str = r#"#[test]
+fn get_ping_returns_pong() {
+    let (status, body) = super::respond("GET /ping HTTP/1.1\r\n\r\n");
+    assert_eq!(status, 200);
+    assert!(body.contains("pong"));
+}
+"#;
+    const INDEX_HTML: &str = r#"<!DOCTYPE html><html><body><pre id="out"></pre><script>
+const BASE = 'http://localhost:1337';
+fetch(BASE + '/ping').then(r => r.json()).then(j => { document.getElementById('out').textContent = j.message; });
+</script></body></html>"#;
+
+    // A model that gets every AI function of a run right the first time
+    struct ScriptedProvider;
+
+    fn scripted_reply(ai_function: &str) -> String {
+        match ai_function {
+            "convert_user_input_to_goal" => "Build a service that answers GET /ping with pong".to_string(),
+            "print_project_scope" => r#"{"is_crud_required": false, "is_user_login_and_logout": false, "is_external_urls_required": false}"#.to_string(),
+            "print_backend_webserver_code" => serde_json::json!({ "files": [{ "path": "src/main.rs", "contents": MAIN_RS }] }).to_string(),
+            "print_improved_webserver_code" => serde_json::json!({
+                "edits": [{ "path": "src/main.rs", "search": "// routes\n", "replace": "// every route of the service\n" }],
+                "files": [],
+            })
+            .to_string(),
+            "print_rest_api_endpoints" => r#"[{"is_route_dynamic": "false", "method": "get", "request_body": "None", "response": {"message": "string"}, "route": "/ping"}]"#.to_string(),
+            "print_api_integration_tests" => API_TESTS_RS.to_string(),
+            "print_frontend_client_code" => INDEX_HTML.to_string(),
+            other => panic!("no scripted reply for {:?}", other),
+        }
+    }
+
+    #[async_trait]
+    impl LlmProvider for ScriptedProvider {
+        fn model(&self) -> &str {
+            "scripted"
+        }
+        async fn complete(&self, messages: Vec<Message>, _settings: &ModelSettings) -> BotResult<APIResponse> {
+            // the prompt is the last message and starts with the signature of the AI function
+            let prompt = messages.last().map(|m| m.content.as_str()).unwrap_or_default();
+            let ai_function = prompt.split_whitespace().skip_while(|word| *word != "fn").nth(1).unwrap_or_default();
+            let ai_function = ai_function.split(|c: char| !(c.is_alphanumeric() || c == '_')).next().unwrap_or_default();
+            let content = scripted_reply(ai_function);
+            Ok(APIResponse { choices: vec![APIChoice { message: APIMessage { content } }], model: None, usage: None })
+        }
+    }
+
+    fn scripted_config() -> ProviderConfig {
+        ProviderConfig { kind: ProviderKind::OpenAi, model: "scripted".to_string(), base_url: String::new(), temperature: 0.1, stream: false, structured: false }
+    }
+
+    fn use_provider(provider: Box<dyn LlmProvider>) {
+        let transport = HttpTransport::new(reqwest::Client::new(), RetryPolicy::default());
+        set_client_context(ClientContext::with_provider(transport, provider, ModelsConfig::default()));
+    }
+
+    async fn run_project(workspace: Workspace, session: Session, user_request: String) -> FactSheet {
+        fs::create_dir_all(workspace.root()).unwrap();
+        fs::write(workspace.root().join("Cargo.toml"), CARGO_TOML).unwrap();
+        let mut managing_agent = ManagingAgent::new(user_request, workspace, session, ApprovePolicy::Always).await.unwrap();
+        managing_agent.execute_project().await.unwrap();
+        managing_agent.factsheet
+    }
+
+    #[tokio::test]
+    async fn test_replayed_run_matches_recorded_run() {
+        let dir = std::env::temp_dir().join(format!("hannah_replay_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let sessions_dir = dir.join("sessions");
+
+        let recorded_session = Session::create(&sessions_dir).unwrap();
+        let cassette = recorded_session.cassette_path();
+        use_provider(Box::new(CassetteProvider::record(cassette.clone(), scripted_config(), Box::new(ScriptedProvider)).unwrap()));
+        let recorded_root = dir.join("ping_service");
+        let workspace = Workspace::from_config(&WorkspaceConfig::default(), Some(recorded_root.clone()));
+        let recorded = run_project(workspace, recorded_session.clone(), "a ping service".to_string()).await;
+
+        // the same request again, into a fresh workspace, answered only from the cassette
+        use_provider(Box::new(CassetteProvider::replay(cassette, scripted_config()).unwrap()));
+        let checkpoint = Session::open(&sessions_dir, &recorded_session.id).unwrap().load().unwrap();
+        let replay_session = Session::create(&sessions_dir).unwrap();
+        let workspace = replay_session.replay_workspace(&checkpoint.workspace);
+        let replayed_root = workspace.root().to_path_buf();
+        assert!(replayed_root.starts_with(&replay_session.dir));
+        let replayed = run_project(workspace, replay_session, checkpoint.user_request).await;
+
+        assert_eq!(replayed.backend_code, recorded.backend_code);
+        assert!(replayed.backend_code.unwrap_or_default().contains("// every route of the service"));
+        assert_eq!(replayed.frontend_code, recorded.frontend_code);
+        assert_eq!(replayed.api_endpoints_schema.map(|routes| routes.len()), Some(1));
+        for file in ["src/main.rs", "src/api_tests.rs", "frontend/index.html", "artifacts/test_report.json"] {
+            assert_eq!(fs::read_to_string(replayed_root.join(file)).unwrap(), fs::read_to_string(recorded_root.join(file)).unwrap(), "{}", file);
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
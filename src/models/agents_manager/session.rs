@@ -0,0 +1,141 @@
+use serde::{Deserialize, Serialize};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::{
+    errors::{BotError, BotResult},
+    models::{
+        agents::agent_traits::{AgentCheckpoint, FactSheet},
+        general::{usage::UsageReport, workspace::Workspace},
+    },
+};
+
+const CHECKPOINT_FILE: &str = "checkpoint.json";
+const CASSETTE_FILE: &str = "cassette.json";
+
+// Everything needed to pick a run back up without repeating LLM calls
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Checkpoint {
+    pub session_id: String,
+    pub user_request: String,
+    pub workspace: Workspace,
+    pub factsheet: FactSheet,
+    pub agents: Vec<AgentCheckpoint>,
+    #[serde(default)]
+    pub usage: UsageReport,
+}
+
+// One run of the manager, stored as <sessions_dir>/<id>/checkpoint.json
+#[derive(Debug, Clone)]
+pub struct Session {
+    pub id: String,
+    pub dir: PathBuf,
+}
+
+impl Session {
+    pub fn create(sessions_dir: &Path) -> BotResult<Self> {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+        let id = format!("{}-{:03}", now.as_secs(), now.subsec_millis());
+        let dir = sessions_dir.join(&id);
+        fs::create_dir_all(&dir)?;
+        Ok(Self { id, dir })
+    }
+    pub fn open(sessions_dir: &Path, id: &str) -> BotResult<Self> {
+        let dir = sessions_dir.join(id);
+        if !dir.join(CHECKPOINT_FILE).exists() {
+            return Err(BotError::Config(format!("no checkpoint found for session {} in {}", id, sessions_dir.display())));
+        }
+        Ok(Self { id: id.to_string(), dir })
+    }
+    // Every session that has a checkpoint, oldest first (ids start with a timestamp)
+    pub fn list(sessions_dir: &Path) -> BotResult<Vec<Self>> {
+        if !sessions_dir.exists() {
+            return Ok(vec![]);
+        }
+        let mut sessions = vec![];
+        for entry in fs::read_dir(sessions_dir)? {
+            let dir = entry?.path();
+            if dir.join(CHECKPOINT_FILE).exists() {
+                let id = dir.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+                sessions.push(Self { id, dir });
+            }
+        }
+        sessions.sort_by(|a, b| a.id.cmp(&b.id));
+        Ok(sessions)
+    }
+    // LLM calls of the session are recorded here so `replay` can run it again offline
+    pub fn cassette_path(&self) -> PathBuf {
+        self.dir.join(CASSETTE_FILE)
+    }
+    // Where a replay into this session rebuilds the recorded project: a fresh directory with the
+    // same name, since the name ends up in Cargo.toml and so in every prompt
+    pub fn replay_workspace(&self, recorded: &Workspace) -> Workspace {
+        let name = recorded.root.file_name().map(PathBuf::from).unwrap_or(PathBuf::from("workspace"));
+        recorded.relocated(self.dir.join("replay").join(name))
+    }
+    // Write to a temp file first so a crash mid-save never leaves a half written checkpoint
+    pub fn save(&self, checkpoint: &Checkpoint) -> BotResult<()> {
+        let contents = serde_json::to_string_pretty(checkpoint)
+            .map_err(|e| BotError::Config(format!("could not serialize checkpoint: {}", e)))?;
+        let tmp_path = self.dir.join(format!("{}.tmp", CHECKPOINT_FILE));
+        fs::write(&tmp_path, contents)?;
+        fs::rename(&tmp_path, self.dir.join(CHECKPOINT_FILE))?;
+        Ok(())
+    }
+    pub fn load(&self) -> BotResult<Checkpoint> {
+        let path = self.dir.join(CHECKPOINT_FILE);
+        let contents = fs::read_to_string(&path)?;
+        serde_json::from_str(&contents)
+            .map_err(|e| BotError::Config(format!("invalid checkpoint {}: {}", path.display(), e)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{agent_basic::basic_agent::AgentState, general::workspace::WorkspaceConfig};
+
+    #[test]
+    fn test_checkpoint_round_trip() {
+        let sessions_dir = std::env::temp_dir().join(format!("hannah_sessions_{}", std::process::id()));
+        let session = Session::create(&sessions_dir).unwrap();
+        let checkpoint = Checkpoint {
+            session_id: session.id.clone(),
+            user_request: "a todo app".to_string(),
+            workspace: Workspace::from_config(&WorkspaceConfig::default(), None),
+            factsheet: FactSheet {
+                project_description: "build a todo app".to_string(),
+                project_scope: None,
+                external_urls: None,
+                backend_code: Some("fn main() {}".to_string()),
+                api_endpoints_schema: None,
+                frontend_code: None,
+                test_failures: None,
+                lint_count: None,
+            },
+            agents: vec![AgentCheckpoint {
+                position: "Backend Developer".to_string(),
+                state: AgentState::UnitTesting,
+                bug_count: 1,
+                bug_errors: Some("error[E0425]".to_string()),
+                memory: vec![],
+            }],
+            usage: UsageReport::default(),
+        };
+        session.save(&checkpoint).unwrap();
+
+        let reopened = Session::open(&sessions_dir, &session.id).unwrap();
+        let loaded = reopened.load().unwrap();
+        assert_eq!(loaded.factsheet, checkpoint.factsheet);
+        assert_eq!(loaded.agents, checkpoint.agents);
+        assert!(Session::open(&sessions_dir, "missing").is_err());
+        let listed = Session::list(&sessions_dir).unwrap();
+        assert_eq!(listed.len(), 1);
+        assert_eq!(listed[0].id, session.id);
+
+        fs::remove_dir_all(&sessions_dir).unwrap();
+    }
+}
@@ -0,0 +1,48 @@
+use serde::{Deserialize, Serialize};
+use std::{fs, path::{Path, PathBuf}};
+
+use crate::{apis::{client::HttpConfig, retry::RetryPolicy, routing::ModelsConfig}, errors::{BotError, BotResult}, helpers::{dependencies::DependencyPolicy, lint::LintPolicy}};
+use super::{usage::PricingConfig, workspace::WorkspaceConfig};
+
+pub const DEFAULT_CONFIG_PATH: &str = "hannah.json";
+pub const DEFAULT_SESSIONS_DIR: &str = ".hannah/sessions";
+
+// Contents of the JSON config file. Every section is optional.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct AppConfig {
+    #[serde(default)]
+    pub workspace: WorkspaceConfig,
+    pub sessions_dir: Option<PathBuf>,
+    #[serde(default)]
+    pub pricing: PricingConfig,
+    #[serde(default)]
+    pub retry: RetryPolicy,
+    #[serde(default)]
+    pub http: HttpConfig,
+    #[serde(default)]
+    pub models: ModelsConfig,
+    #[serde(default)]
+    pub dependencies: DependencyPolicy,
+    #[serde(default)]
+    pub lints: LintPolicy,
+}
+
+impl AppConfig {
+    pub fn sessions_dir(&self) -> PathBuf {
+        self.sessions_dir.clone().unwrap_or(PathBuf::from(DEFAULT_SESSIONS_DIR))
+    }
+    pub fn load(path: &Path) -> BotResult<Self> {
+        let contents = fs::read_to_string(path)
+            .map_err(|e| BotError::Config(format!("could not read config {}: {}", path.display(), e)))?;
+        serde_json::from_str(&contents)
+            .map_err(|e| BotError::Config(format!("invalid config {}: {}", path.display(), e)))
+    }
+    // An explicit path must exist; the default hannah.json is only used when present
+    pub fn load_or_default(path: Option<&Path>) -> BotResult<Self> {
+        match path {
+            Some(path) => Self::load(path),
+            None if Path::new(DEFAULT_CONFIG_PATH).exists() => Self::load(Path::new(DEFAULT_CONFIG_PATH)),
+            None => Ok(Self::default()),
+        }
+    }
+}
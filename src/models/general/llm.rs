@@ -1,5 +1,5 @@
 use serde::{Deserialize, Serialize};
-#[derive(Serialize, Deserialize,Clone,Debug)]
+#[derive(Serialize, Deserialize,Clone,Debug,PartialEq)]
 pub struct Message {
     pub role : String,
     pub content : String,
@@ -10,6 +10,20 @@ pub struct ChatCompletion {
     pub model : String,
     pub messages : Vec<Message>,
     pub temperature : f32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stream : Option<bool>,
+    // {"include_usage": true} makes OpenAI send token counts in the last chunk
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stream_options : Option<serde_json::Value>,
+    // {"type": "json_schema", ...} for structured output
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub response_format : Option<serde_json::Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_tokens : Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub top_p : Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stop : Option<Vec<String>>,
 }
 
 #[derive( Deserialize)] 
@@ -22,7 +36,127 @@ pub struct  APIChoice {
     pub message: APIMessage
 }
 
+// Token counts as reported by the provider
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+pub struct Usage {
+    #[serde(default)]
+    pub prompt_tokens : u64,
+    #[serde(default)]
+    pub completion_tokens : u64,
+}
+
+// One `data:` chunk of a streamed chat completion
+#[derive( Deserialize, Default)]
+pub struct StreamDelta {
+    #[serde(default)]
+    pub content : Option<String>,
+}
+
+#[derive( Deserialize)]
+pub struct StreamChoice {
+    #[serde(default)]
+    pub delta : StreamDelta,
+}
+
+#[derive( Deserialize)]
+pub struct StreamChunk {
+    #[serde(default)]
+    pub choices : Vec<StreamChoice>,
+    #[serde(default)]
+    pub model : Option<String>,
+    #[serde(default)]
+    pub usage : Option<Usage>,
+}
+
 #[derive( Deserialize)]
 pub struct APIResponse {
     pub choices : Vec<APIChoice>,
-}
\ No newline at end of file
+    #[serde(default)]
+    pub model : Option<String>,
+    #[serde(default)]
+    pub usage : Option<Usage>,
+}
+
+// Anthropic Messages API
+#[derive(Serialize, Clone)]
+pub struct AnthropicRequest {
+    pub model : String,
+    pub max_tokens : u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub system : Option<String>,
+    pub messages : Vec<Message>,
+    pub temperature : f32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub top_p : Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stop_sequences : Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stream : Option<bool>,
+    // structured output is a single forced tool call
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tools : Option<Vec<AnthropicTool>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tool_choice : Option<serde_json::Value>,
+}
+
+#[derive(Serialize, Clone)]
+pub struct AnthropicTool {
+    pub name : String,
+    pub description : String,
+    pub input_schema : serde_json::Value,
+}
+
+#[derive( Deserialize)]
+pub struct AnthropicContent {
+    #[serde(rename = "type")]
+    pub content_type : String,
+    #[serde(default)]
+    pub text : String,
+    // arguments of a tool_use block
+    #[serde(default)]
+    pub input : Option<serde_json::Value>,
+}
+
+#[derive( Deserialize)]
+pub struct AnthropicUsage {
+    #[serde(default)]
+    pub input_tokens : u64,
+    #[serde(default)]
+    pub output_tokens : u64,
+}
+
+#[derive( Deserialize)]
+pub struct AnthropicResponse {
+    pub content : Vec<AnthropicContent>,
+    #[serde(default)]
+    pub model : Option<String>,
+    pub usage : Option<AnthropicUsage>,
+}
+
+// Streaming events: message_start carries the input tokens, content_block_delta the text and
+// message_delta the output tokens
+#[derive( Deserialize)]
+pub struct AnthropicStreamMessage {
+    #[serde(default)]
+    pub model : Option<String>,
+    pub usage : Option<AnthropicUsage>,
+}
+
+#[derive( Deserialize)]
+pub struct AnthropicDelta {
+    #[serde(default)]
+    pub text : Option<String>,
+    // tool arguments arrive as pieces of JSON text
+    #[serde(default)]
+    pub partial_json : Option<String>,
+}
+
+#[derive( Deserialize)]
+pub struct AnthropicStreamEvent {
+    #[serde(rename = "type")]
+    pub event_type : String,
+    pub message : Option<AnthropicStreamMessage>,
+    pub delta : Option<AnthropicDelta>,
+    pub usage : Option<AnthropicUsage>,
+    pub error : Option<serde_json::Value>,
+}
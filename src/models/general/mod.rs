@@ -1 +1,5 @@
-pub mod llm;
\ No newline at end of file
+pub mod config;
+pub mod llm;
+pub mod schema;
+pub mod usage;
+pub mod workspace;
\ No newline at end of file
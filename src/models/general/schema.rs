@@ -0,0 +1,101 @@
+use serde_json::{json, Map, Value};
+
+// Types the model is asked to produce. The schema is sent to providers that support structured
+// output so the answer is checked by the API instead of only by the prompt.
+pub trait JsonSchema {
+    fn json_schema() -> Value;
+}
+
+impl JsonSchema for bool {
+    fn json_schema() -> Value {
+        json!({ "type": "boolean" })
+    }
+}
+
+impl JsonSchema for String {
+    fn json_schema() -> Value {
+        json!({ "type": "string" })
+    }
+}
+
+// Any JSON value
+impl JsonSchema for Value {
+    fn json_schema() -> Value {
+        json!({})
+    }
+}
+
+impl<T: JsonSchema> JsonSchema for Vec<T> {
+    fn json_schema() -> Value {
+        json!({ "type": "array", "items": T::json_schema() })
+    }
+}
+
+// An object with every property required and nothing else allowed, as strict mode expects
+pub fn object_schema(properties: &[(&str, Value)]) -> Value {
+    let required: Vec<&str> = properties.iter().map(|(name, _)| *name).collect();
+    let properties: Map<String, Value> = properties.iter().map(|(name, schema)| (name.to_string(), schema.clone())).collect();
+    json!({
+        "type": "object",
+        "properties": properties,
+        "required": required,
+        "additionalProperties": false,
+    })
+}
+
+// Strict mode cannot express free-form values
+fn has_free_form(schema: &Value) -> bool {
+    match schema {
+        Value::Object(map) if map.is_empty() => true,
+        Value::Object(map) => map.values().any(has_free_form),
+        Value::Array(items) => items.iter().any(has_free_form),
+        _ => false,
+    }
+}
+
+// The schema as handed to a provider. Both OpenAI and Anthropic want an object at the top, so
+// anything else is wrapped in {"value": ...} and unwrapped again from the answer.
+#[derive(Debug, Clone, PartialEq)]
+pub struct OutputSchema {
+    pub name: String,
+    pub schema: Value,
+    pub strict: bool,
+    wrapped: bool,
+}
+
+impl OutputSchema {
+    pub fn of<T: JsonSchema>(name: &str) -> Self {
+        let schema = T::json_schema();
+        let wrapped = schema.get("type").and_then(Value::as_str) != Some("object");
+        let schema = if wrapped { object_schema(&[("value", schema)]) } else { schema };
+        Self { name: name.to_string(), strict: !has_free_form(&schema), schema, wrapped }
+    }
+    // Left alone when it does not parse, so the caller's decoding reports the real problem
+    pub fn unwrap_output(&self, content: String) -> String {
+        if !self.wrapped {
+            return content;
+        }
+        match serde_json::from_str::<Value>(&content) {
+            Ok(Value::Object(mut map)) if map.contains_key("value") => map.remove("value").unwrap_or_default().to_string(),
+            _ => content,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_non_object_schemas_are_wrapped_and_unwrapped() {
+        let urls = OutputSchema::of::<Vec<String>>("print_site_urls");
+        assert_eq!(urls.schema["properties"]["value"]["items"]["type"], "string");
+        assert_eq!(urls.schema["required"], json!(["value"]));
+        assert!(urls.strict);
+        assert_eq!(urls.unwrap_output(r#"{"value":["https://a.com"]}"#.to_string()), r#"["https://a.com"]"#);
+        assert_eq!(urls.unwrap_output("not json".to_string()), "not json");
+
+        let any = OutputSchema::of::<Vec<Value>>("print_anything");
+        assert!(!any.strict);
+    }
+}
@@ -0,0 +1,211 @@
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, sync::Mutex};
+
+use crate::errors::{BotError, BotResult};
+use super::llm::Usage;
+
+// USD per million tokens
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct ModelPrice {
+    pub input_per_mtok: f64,
+    pub output_per_mtok: f64,
+}
+
+// The "pricing" section of the config file
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct PricingConfig {
+    #[serde(default)]
+    pub prices: HashMap<String, ModelPrice>,
+    pub budget_usd: Option<f64>,
+}
+
+const DEFAULT_PRICES: &[(&str, f64, f64)] = &[
+    ("gpt-4", 30.0, 60.0),
+    ("gpt-4-turbo", 10.0, 30.0),
+    ("gpt-4o", 2.5, 10.0),
+    ("gpt-4o-mini", 0.15, 0.6),
+    ("gpt-3.5-turbo", 0.5, 1.5),
+    ("claude-3-opus", 15.0, 75.0),
+    ("claude-3-5-sonnet", 3.0, 15.0),
+    ("claude-3-5-haiku", 0.8, 4.0),
+];
+
+impl PricingConfig {
+    // Configured prices win over the built-in ones. The longest matching prefix is used so dated
+    // names like gpt-4o-2024-08-06 find gpt-4o. Unknown models, local ones included, are free.
+    pub fn price_for(&self, model: &str) -> Option<ModelPrice> {
+        let configured = self.prices.iter().map(|(name, price)| (name.as_str(), *price));
+        let built_in = DEFAULT_PRICES.iter().map(|(name, input, output)| {
+            (*name, ModelPrice { input_per_mtok: *input, output_per_mtok: *output })
+        });
+        let longest = |prices: Vec<(&str, ModelPrice)>| {
+            prices
+                .into_iter()
+                .filter(|(name, _)| model.starts_with(name))
+                .max_by_key(|(name, _)| name.len())
+                .map(|(_, price)| price)
+        };
+        longest(configured.collect()).or_else(|| longest(built_in.collect()))
+    }
+    pub fn cost(&self, model: &str, usage: &Usage) -> f64 {
+        match self.price_for(model) {
+            Some(price) => {
+                (usage.prompt_tokens as f64 * price.input_per_mtok + usage.completion_tokens as f64 * price.output_per_mtok) / 1_000_000.0
+            }
+            None => 0.0,
+        }
+    }
+}
+
+// Totals for one agent position, AI function and model
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct UsageEntry {
+    pub position: String,
+    pub ai_function: String,
+    pub model: String,
+    pub calls: u32,
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+    pub cost_usd: f64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct UsageReport {
+    pub entries: Vec<UsageEntry>,
+}
+
+impl UsageReport {
+    pub fn record(&mut self, position: &str, ai_function: &str, model: &str, usage: &Usage, cost_usd: f64) {
+        let existing = self
+            .entries
+            .iter_mut()
+            .find(|e| e.position == position && e.ai_function == ai_function && e.model == model);
+        let entry = match existing {
+            Some(entry) => entry,
+            None => {
+                self.entries.push(UsageEntry {
+                    position: position.to_string(),
+                    ai_function: ai_function.to_string(),
+                    model: model.to_string(),
+                    ..Default::default()
+                });
+                self.entries.last_mut().unwrap()
+            }
+        };
+        entry.calls += 1;
+        entry.prompt_tokens += usage.prompt_tokens;
+        entry.completion_tokens += usage.completion_tokens;
+        entry.cost_usd += cost_usd;
+    }
+    pub fn total_cost(&self) -> f64 {
+        self.entries.iter().map(|e| e.cost_usd).sum()
+    }
+    pub fn total_tokens(&self) -> u64 {
+        self.entries.iter().map(|e| e.prompt_tokens + e.completion_tokens).sum()
+    }
+    // Agents in the order they first spent anything
+    pub fn cost_by_agent(&self) -> Vec<(String, f64)> {
+        let mut by_agent: Vec<(String, f64)> = vec![];
+        for entry in &self.entries {
+            match by_agent.iter_mut().find(|(position, _)| *position == entry.position) {
+                Some((_, cost)) => *cost += entry.cost_usd,
+                None => by_agent.push((entry.position.clone(), entry.cost_usd)),
+            }
+        }
+        by_agent
+    }
+    pub fn summary(&self) -> String {
+        let mut lines: Vec<String> = self
+            .entries
+            .iter()
+            .map(|e| {
+                format!(
+                    "{} / {} ({}): {} calls, {} in + {} out tokens, ${:.4}",
+                    e.position, e.ai_function, e.model, e.calls, e.prompt_tokens, e.completion_tokens, e.cost_usd
+                )
+            })
+            .collect();
+        for (position, cost) in self.cost_by_agent() {
+            lines.push(format!("{} total: ${:.4}", position, cost));
+        }
+        lines.push(format!("Run total: {} tokens, ${:.4}", self.total_tokens(), self.total_cost()));
+        lines.join("\n")
+    }
+}
+
+// One ledger per process. LLM calls are made from free functions all over the agents, so this is
+// simpler than handing a tracker to every one of them.
+#[derive(Default)]
+struct Ledger {
+    pricing: PricingConfig,
+    report: UsageReport,
+}
+
+static LEDGER: Mutex<Option<Ledger>> = Mutex::new(None);
+
+fn with_ledger<T>(f: impl FnOnce(&mut Ledger) -> T) -> T {
+    let mut guard = LEDGER.lock().unwrap_or_else(|e| e.into_inner());
+    f(guard.get_or_insert_with(Ledger::default))
+}
+
+pub fn set_pricing(pricing: PricingConfig) {
+    with_ledger(|ledger| ledger.pricing = pricing);
+}
+
+// Picks up the spending of an earlier part of the same session
+pub fn restore_usage(report: UsageReport) {
+    with_ledger(|ledger| ledger.report = report);
+}
+
+pub fn usage_report() -> UsageReport {
+    with_ledger(|ledger| ledger.report.clone())
+}
+
+pub fn record_usage(position: &str, ai_function: &str, model: &str, usage: &Usage) {
+    with_ledger(|ledger| {
+        let cost = ledger.pricing.cost(model, usage);
+        ledger.report.record(position, ai_function, model, usage, cost);
+    });
+}
+
+// Checked before every call, so a run overshoots its budget by at most the call that crossed it
+pub fn check_budget() -> BotResult<()> {
+    with_ledger(|ledger| match ledger.pricing.budget_usd {
+        Some(budget_usd) if ledger.report.total_cost() >= budget_usd => Err(BotError::BudgetExceeded {
+            spent_usd: ledger.report.total_cost(),
+            budget_usd,
+        }),
+        _ => Ok(()),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_price_lookup_and_cost() {
+        let mut pricing = PricingConfig::default();
+        assert_eq!(pricing.price_for("gpt-4o-mini-2024-07-18").unwrap().input_per_mtok, 0.15);
+        assert_eq!(pricing.price_for("gpt-4-0613").unwrap().input_per_mtok, 30.0);
+        assert!(pricing.price_for("llama3").is_none());
+
+        pricing.prices.insert("llama3".to_string(), ModelPrice { input_per_mtok: 1.0, output_per_mtok: 2.0 });
+        let usage = Usage { prompt_tokens: 1_000_000, completion_tokens: 500_000 };
+        assert_eq!(pricing.cost("llama3", &usage), 2.0);
+    }
+
+    #[test]
+    fn test_report_aggregates_per_agent_and_function() {
+        let mut report = UsageReport::default();
+        let usage = Usage { prompt_tokens: 100, completion_tokens: 50 };
+        report.record("Backend Developer", "print_fixed_code", "gpt-4", &usage, 0.5);
+        report.record("Backend Developer", "print_fixed_code", "gpt-4", &usage, 0.5);
+        report.record("Solution Architect", "print_project_scope", "gpt-4", &usage, 0.25);
+        assert_eq!(report.entries.len(), 2);
+        assert_eq!(report.entries[0].calls, 2);
+        assert_eq!(report.total_tokens(), 450);
+        assert_eq!(report.total_cost(), 1.25);
+        assert_eq!(report.cost_by_agent(), vec![("Backend Developer".to_string(), 1.0), ("Solution Architect".to_string(), 0.25)]);
+    }
+}
@@ -0,0 +1,95 @@
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+// Where the generated project lives. Relative paths in the config are resolved
+// against `root`, so several workspaces can be driven side by side.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Workspace {
+    pub root: PathBuf,
+    pub template_path: PathBuf,
+    pub main_file: PathBuf,
+    pub artifact_dir: PathBuf,
+    pub frontend_file: PathBuf,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct WorkspaceConfig {
+    pub root: Option<PathBuf>,
+    pub template_path: Option<PathBuf>,
+    pub main_file: Option<PathBuf>,
+    pub artifact_dir: Option<PathBuf>,
+    pub frontend_file: Option<PathBuf>,
+}
+
+impl Workspace {
+    pub fn from_config(config: &WorkspaceConfig, root_override: Option<PathBuf>) -> Self {
+        let root = root_override
+            .or(config.root.clone())
+            .unwrap_or(PathBuf::from("workspace"));
+        let resolve = |path: &Option<PathBuf>, default: &str| -> PathBuf {
+            let path = path.clone().unwrap_or(PathBuf::from(default));
+            if path.is_absolute() { path } else { root.join(path) }
+        };
+        Self {
+            template_path: resolve(&config.template_path, "src/gpt_created.rs"),
+            main_file: resolve(&config.main_file, "src/main.rs"),
+            artifact_dir: resolve(&config.artifact_dir, "artifacts"),
+            frontend_file: resolve(&config.frontend_file, "frontend/index.html"),
+            root,
+        }
+    }
+    // The same layout under another root. Paths outside the old root, such as an absolute
+    // template path, stay where they are.
+    pub fn relocated(&self, root: PathBuf) -> Self {
+        let rebase = |path: &PathBuf| match path.strip_prefix(&self.root) {
+            Ok(relative) => root.join(relative),
+            Err(_) => path.clone(),
+        };
+        Self {
+            template_path: rebase(&self.template_path),
+            main_file: rebase(&self.main_file),
+            artifact_dir: rebase(&self.artifact_dir),
+            frontend_file: rebase(&self.frontend_file),
+            root,
+        }
+    }
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+    pub fn api_endpoints_path(&self) -> PathBuf {
+        self.artifact_dir.join("api_endpoints.json")
+    }
+    // Integration tests of the backend, a module next to the main file so they can reach its handlers
+    pub fn api_tests_path(&self) -> PathBuf {
+        self.main_file.with_file_name("api_tests.rs")
+    }
+    // stdout and stderr of the backend while it is under test
+    pub fn server_log_path(&self) -> PathBuf {
+        self.artifact_dir.join("server.log")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_relative_paths_resolve_against_root() {
+        let config = WorkspaceConfig {
+            root: Some(PathBuf::from("/tmp/ignored")),
+            template_path: Some(PathBuf::from("/opt/templates/main.rs")),
+            main_file: None,
+            artifact_dir: Some(PathBuf::from("out")),
+            frontend_file: None,
+        };
+        let workspace = Workspace::from_config(&config, Some(PathBuf::from("/srv/todo_app")));
+        assert_eq!(workspace.root(), Path::new("/srv/todo_app"));
+        assert_eq!(workspace.template_path, PathBuf::from("/opt/templates/main.rs"));
+        assert_eq!(workspace.main_file, PathBuf::from("/srv/todo_app/src/main.rs"));
+        assert_eq!(workspace.api_endpoints_path(), PathBuf::from("/srv/todo_app/out/api_endpoints.json"));
+
+        let moved = workspace.relocated(PathBuf::from("/tmp/replay/todo_app"));
+        assert_eq!(moved.main_file, PathBuf::from("/tmp/replay/todo_app/src/main.rs"));
+        assert_eq!(moved.template_path, PathBuf::from("/opt/templates/main.rs"));
+    }
+}